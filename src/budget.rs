@@ -0,0 +1,201 @@
+use std::collections::{HashMap, VecDeque};
+use tokio::time::{Duration, Instant};
+
+use crate::config::{BudgetConfig, BudgetOverflow, CategoryRateLimit};
+
+// What to do with an item given the budget
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Admission {
+    Send,
+    // Leave it unmarked for a later fetch
+    Defer,
+    // Mark it as seen without announcing it
+    Suppress,
+}
+
+// Sliding-window counter limiting how many announcements go out per window
+pub struct AnnounceBudget {
+    max_announcements: usize,
+    window: Duration,
+    overflow: BudgetOverflow,
+    sent: VecDeque<Instant>,
+}
+
+impl AnnounceBudget {
+    pub fn new(config: &BudgetConfig) -> Self {
+        Self {
+            max_announcements: config.max_announcements,
+            window: Duration::from_secs(config.window_secs),
+            overflow: config.overflow,
+            sent: VecDeque::new(),
+        }
+    }
+
+    // Whether an item may be announced now, and if not what the overflow mode says
+    pub fn admit(&mut self, reserved: usize) -> Admission {
+        if self.has_room(reserved) {
+            return Admission::Send;
+        }
+        match self.overflow {
+            BudgetOverflow::Defer => Admission::Defer,
+            BudgetOverflow::Summarize => Admission::Suppress,
+        }
+    }
+
+    // Whether one more announcement fits the window, with `reserved` others already on their
+    // way. Nothing is consumed, only announcements that were actually sent count.
+    pub fn has_room(&mut self, reserved: usize) -> bool {
+        let now = Instant::now();
+
+        // Forget announcements that have slid out of the window
+        while let Some(oldest) = self.sent.front() {
+            if now.duration_since(*oldest) >= self.window {
+                self.sent.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        self.sent.len() + reserved < self.max_announcements
+    }
+
+    // Count a sent announcement against the current window
+    pub fn record(&mut self) {
+        self.sent.push_back(Instant::now());
+    }
+}
+
+// Allows bursts of up to capacity, then regains capacity tokens per period
+pub struct TokenBucket {
+    capacity: f64,
+    // Tokens regained per second
+    refill_rate: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: u32, period: Duration) -> Self {
+        Self {
+            capacity: capacity as f64,
+            refill_rate: capacity as f64 / period.as_secs_f64(),
+            tokens: capacity as f64,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    // Try to take a token, returns false while the bucket is empty
+    pub fn try_take(&mut self) -> bool {
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.refilled_at).as_secs_f64() * self.refill_rate).min(self.capacity);
+        self.refilled_at = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+// A token bucket per category, so a bulk import in one category can't bury the others
+pub struct CategoryLimiter {
+    max_announcements: u32,
+    period: Duration,
+    buckets: HashMap<String, TokenBucket>,
+}
+
+impl CategoryLimiter {
+    pub fn new(config: &CategoryRateLimit) -> Self {
+        Self {
+            max_announcements: config.max_announcements,
+            period: Duration::from_secs(config.per_secs),
+            buckets: HashMap::new(),
+        }
+    }
+
+    pub fn try_take(&mut self, category: Option<&str>) -> bool {
+        // Items without a category share one bucket
+        let key = category.unwrap_or_default().trim().to_lowercase();
+        self.buckets.entry(key)
+            .or_insert_with(|| TokenBucket::new(self.max_announcements, self.period))
+            .try_take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(max_announcements: usize, window_secs: u64, overflow: BudgetOverflow) -> AnnounceBudget {
+        AnnounceBudget::new(&BudgetConfig { max_announcements, window_secs, overflow })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn room_frees_up_once_the_window_slides() {
+        let mut budget = budget(2, 60, BudgetOverflow::Defer);
+        budget.record();
+        tokio::time::advance(Duration::from_secs(30)).await;
+        budget.record();
+        assert!(!budget.has_room(0));
+
+        // Just before the first announcement leaves the window
+        tokio::time::advance(Duration::from_secs(29)).await;
+        assert!(!budget.has_room(0));
+
+        // Exactly one window after the first announcement
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(budget.has_room(0));
+        budget.record();
+        assert!(!budget.has_room(0));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn checking_room_consumes_nothing() {
+        let mut budget = budget(1, 60, BudgetOverflow::Defer);
+        for _ in 0..5 {
+            assert!(budget.has_room(0));
+        }
+        budget.record();
+        assert!(!budget.has_room(0));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reserved_announcements_count() {
+        let mut budget = budget(3, 60, BudgetOverflow::Defer);
+        budget.record();
+        assert!(budget.has_room(1));
+        assert!(!budget.has_room(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn defer_leaves_items_for_later() {
+        let mut budget = budget(1, 60, BudgetOverflow::Defer);
+        assert_eq!(budget.admit(0), Admission::Send);
+        budget.record();
+        assert_eq!(budget.admit(0), Admission::Defer);
+
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert_eq!(budget.admit(0), Admission::Send);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn summarize_suppresses_items() {
+        let mut budget = budget(1, 60, BudgetOverflow::Summarize);
+        assert_eq!(budget.admit(0), Admission::Send);
+        budget.record();
+        assert_eq!(budget.admit(0), Admission::Suppress);
+        assert_eq!(budget.admit(0), Admission::Suppress);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn category_buckets_are_independent() {
+        let mut limiter = CategoryLimiter::new(&CategoryRateLimit { max_announcements: 1, per_secs: 60 });
+        assert!(limiter.try_take(Some("Movies")));
+        assert!(!limiter.try_take(Some(" movies ")));
+        assert!(limiter.try_take(Some("TV")));
+
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert!(limiter.try_take(Some("Movies")));
+    }
+}
@@ -0,0 +1,87 @@
+// Hard cap for !last so a single command can't flood the channel
+pub const MAX_REPLAY: usize = 10;
+
+#[derive(Debug, PartialEq)]
+pub enum BotCommand {
+    // !last [N], repost the last N announcements
+    Last(usize),
+    // !latest, repost the most recent announcement
+    Latest,
+    // !stats, seen count, uptime and time since the last fetch
+    Stats,
+    // !help, list the commands
+    Help,
+    // Admin only: stop and restart fetching and announcing, re-read the config file
+    Pause,
+    Resume,
+    Reload,
+}
+
+impl BotCommand {
+    pub fn is_admin(&self) -> bool {
+        matches!(self, BotCommand::Pause | BotCommand::Resume | BotCommand::Reload)
+    }
+}
+
+pub fn help() -> String {
+    format!("Commands: !latest, !last [N] (max {}), !stats, !help. Admins: !pause, !resume, !reload", MAX_REPLAY)
+}
+
+pub fn parse_command(body: &str) -> Option<BotCommand> {
+    let mut parts = body.split_whitespace();
+
+    match parts.next()? {
+        "!last" => {
+            let count = match parts.next() {
+                Some(count) => count.parse::<usize>().ok()?,
+                None => 1,
+            };
+            Some(BotCommand::Last(count.clamp(1, MAX_REPLAY)))
+        }
+        "!latest" => Some(BotCommand::Latest),
+        "!stats" => Some(BotCommand::Stats),
+        "!help" => Some(BotCommand::Help),
+        "!pause" => Some(BotCommand::Pause),
+        "!resume" => Some(BotCommand::Resume),
+        "!reload" => Some(BotCommand::Reload),
+        _ => None,
+    }
+}
+
+// Match a nick or nick!user@host against a mask, case-insensitive, with * and ? wildcards.
+// A mask without '!' is compared against the nick only.
+pub fn matches_mask(mask: &str, nick: &str, hostmask: &str) -> bool {
+    let subject = if mask.contains('!') { hostmask } else { nick };
+    matches_hostmask(subject, mask)
+}
+
+// Match a full nick!user@host prefix against a pattern, case-insensitive, with * and ? wildcards
+pub fn matches_hostmask(prefix: &str, pattern: &str) -> bool {
+    glob_match(&pattern.to_lowercase(), &prefix.to_lowercase())
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position after the last *, and the text position it was matched up to
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p + 1, t));
+            p += 1;
+        } else if let Some((after_star, matched)) = star {
+            p = after_star;
+            t = matched + 1;
+            star = Some((after_star, t));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
@@ -0,0 +1,183 @@
+// Local control interface over a Unix domain socket.
+//
+// The protocol is line based: each line sent by the client is one command, and
+// the bot answers every command with a single line of text. Replies start with
+// "ok" or "error". Supported commands:
+//
+//   stats       Seen-set size, uptime, time since the last API fetch and mute state
+//   fetchnow    Fetch the API on the next tick, ignoring the rate limit interval
+//   mute <N>    Stop fetching and announcing for N minutes (0 unmutes)
+//   unmute      Resume announcing immediately
+//   pause       Stop fetching and announcing until resumed
+//   resume      Undo pause
+//   reload      Re-read the config file and apply the API and budget settings
+//
+// The socket is created with mode 0600, so only the user running the bot (and
+// root) can connect. Example: `echo stats | socat - UNIX-CONNECT:announcarr.sock`
+use std::fs;
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{sleep, Duration};
+use tracing::{debug, error, info};
+
+// Pause after a failed accept, so a persistent error (e.g. out of file descriptors) doesn't spin
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Debug, PartialEq)]
+pub enum ControlCommand {
+    Stats,
+    FetchNow,
+    Mute(u64),
+    Unmute,
+    Pause,
+    Resume,
+    Reload,
+}
+
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    pub reply: oneshot::Sender<String>,
+}
+
+fn parse_command(line: &str) -> Result<ControlCommand, String> {
+    let mut parts = line.split_whitespace();
+
+    match parts.next() {
+        Some("stats") => Ok(ControlCommand::Stats),
+        Some("fetchnow") => Ok(ControlCommand::FetchNow),
+        Some("mute") => match parts.next().map(str::parse::<u64>) {
+            Some(Ok(minutes)) => Ok(ControlCommand::Mute(minutes)),
+            _ => Err("usage: mute <minutes>".to_string()),
+        },
+        Some("unmute") => Ok(ControlCommand::Unmute),
+        Some("pause") => Ok(ControlCommand::Pause),
+        Some("resume") => Ok(ControlCommand::Resume),
+        Some("reload") => Ok(ControlCommand::Reload),
+        Some(other) => Err(format!("unknown command '{}'", other)),
+        None => Err("empty command".to_string()),
+    }
+}
+
+// Bind the control socket, commands are forwarded to tx
+pub fn spawn(path: &str, tx: mpsc::Sender<ControlRequest>) -> io::Result<()> {
+    // Remove a stale socket left behind by a previous run, but never anything else
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists and is not a socket", path)));
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    // Bind inside a private 0700 directory and only move the socket into place once it's
+    // 0600, so other local users never get a window in which they could connect. The
+    // directory must not exist yet, whatever is there is left alone.
+    let private_dir = format!("{}.tmp", path);
+    fs::DirBuilder::new().mode(0o700).create(&private_dir).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => io::Error::new(e.kind(), format!("{} already exists, remove it if it's left over", private_dir)),
+        _ => e,
+    })?;
+    let private_path = Path::new(&private_dir).join("socket");
+    let bound = UnixListener::bind(&private_path).and_then(|listener| {
+        fs::set_permissions(&private_path, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&private_path, path)?;
+        Ok(listener)
+    });
+    // Only our own directory, with at most our own socket in it
+    let _ = fs::remove_file(&private_path);
+    fs::remove_dir(&private_dir)?;
+    let listener = bound?;
+    info!("🎛️  Control socket listening on {}", path);
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle_connection(stream, tx.clone()));
+                }
+                Err(e) => {
+                    error!("Failed to accept control connection: {}", e);
+                    sleep(ACCEPT_RETRY_DELAY).await;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+async fn handle_connection(stream: UnixStream, tx: mpsc::Sender<ControlRequest>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        debug!("Control command: {}", line);
+
+        let response = match parse_command(&line) {
+            Ok(command) => {
+                let (reply, response) = oneshot::channel();
+                if tx.send(ControlRequest { command, reply }).await.is_err() {
+                    return;
+                }
+                response.await.unwrap_or_else(|_| "error: no response".to_string())
+            }
+            Err(e) => format!("error: {}", e),
+        };
+
+        if writer.write_all(format!("{}\n", response).as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn socket_path(dir: &tempfile::TempDir) -> String {
+        dir.path().join("announcarr.sock").to_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn stale_socket_is_replaced_with_a_private_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = socket_path(&dir);
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+        spawn(&path, mpsc::channel(1).0).unwrap();
+        let metadata = fs::symlink_metadata(&path).unwrap();
+        assert!(metadata.file_type().is_socket());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
+    }
+
+    #[tokio::test]
+    async fn other_files_at_the_path_are_left_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = socket_path(&dir);
+        fs::write(&path, "not a socket").unwrap();
+
+        let e = spawn(&path, mpsc::channel(1).0).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "not a socket");
+    }
+
+    #[tokio::test]
+    async fn existing_private_dir_is_left_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = socket_path(&dir);
+        let private_dir = format!("{}.tmp", path);
+        fs::create_dir(&private_dir).unwrap();
+        fs::write(Path::new(&private_dir).join("keep"), "data").unwrap();
+
+        let e = spawn(&path, mpsc::channel(1).0).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(Path::new(&private_dir).join("keep")).unwrap(), "data");
+        assert!(!Path::new(&path).exists());
+    }
+}
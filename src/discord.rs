@@ -0,0 +1,74 @@
+use reqwest::Client;
+use serde_json::json;
+use tokio::time::Duration;
+use tracing::{debug, info};
+
+use crate::config::{AnnounceConfig, DiscordConfig, Secret};
+use crate::formatting::{format_item, strip_control_codes};
+use crate::notifier::{Notifier, NotifyError};
+use crate::source::Release;
+
+// Discord rejects message content longer than this
+const MAX_CONTENT_LEN: usize = 2000;
+
+// Posts announcements to a Discord webhook
+pub struct DiscordNotifier {
+    client: Client,
+    webhook_url: Secret,
+    announce: AnnounceConfig,
+    dry_run: bool,
+}
+
+impl DiscordNotifier {
+    pub fn new(config: DiscordConfig, announce: AnnounceConfig, dry_run: bool) -> reqwest::Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()?;
+
+        Ok(Self {
+            client,
+            webhook_url: config.webhook_url,
+            announce,
+            dry_run,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for DiscordNotifier {
+    fn name(&self) -> &str {
+        "Discord"
+    }
+
+    async fn notify(&mut self, item: &Release) -> Result<(), NotifyError> {
+        let mut content = strip_control_codes(&format_item(item, &self.announce, false));
+        if content.len() > MAX_CONTENT_LEN {
+            let mut cut = MAX_CONTENT_LEN;
+            while !content.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            content.truncate(cut);
+        }
+
+        if self.dry_run {
+            info!("📝 [dry run] Would post to Discord: {}", content);
+            return Ok(());
+        }
+
+        // Never ping anyone, whatever the release name contains
+        let body = json!({
+            "content": content,
+            "allowed_mentions": { "parse": [] },
+        });
+
+        // The webhook URL holds its token, keep it out of error messages
+        let response = self.client.post(self.webhook_url.expose()).json(&body).send().await
+            .map_err(|e| NotifyError::Http(e.without_url()))?;
+        if !response.status().is_success() {
+            return Err(NotifyError::Rejected(response.status()));
+        }
+
+        debug!("✅ Posted ID {} to Discord", item.id);
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt;
+use std::io;
+
+use crate::config::ConfigError;
+use crate::source::SourceError;
+use crate::store::StoreError;
+
+// Everything that can stop the bot, one variant per subsystem so callers can tell
+// a bad config from an unreachable IRC server or an unwritable announced file
+#[derive(Debug)]
+pub enum Error {
+    Config(ConfigError),
+    Irc(irc::error::Error),
+    // The IRC server closed the connection
+    Disconnected,
+    Source(SourceError),
+    Store(StoreError),
+    // A Discord or Telegram client couldn't be set up
+    Notifier(reqwest::Error),
+    // Listening sockets of the control, metrics and health endpoints
+    Io(io::Error),
+    // A --once run where some sources couldn't be fetched or announced
+    RunFailed(Vec<String>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(e) => write!(f, "{}", e),
+            Error::Irc(e) => write!(f, "IRC error: {}", crate::irc_client::describe_error(e)),
+            Error::Disconnected => write!(f, "IRC connection closed"),
+            Error::Source(e) => write!(f, "API error: {}", e),
+            Error::Store(e) => write!(f, "store error: {}", e),
+            Error::Notifier(e) => write!(f, "failed to create notifier: {}", e),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::RunFailed(failed) => write!(f, "single run failed: {}", failed.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Config(e) => Some(e),
+            Error::Irc(e) => Some(e),
+            Error::Source(e) => Some(e),
+            Error::Store(e) => Some(e),
+            Error::Notifier(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::Disconnected | Error::RunFailed(_) => None,
+        }
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(e: ConfigError) -> Self {
+        Error::Config(e)
+    }
+}
+
+impl From<irc::error::Error> for Error {
+    fn from(e: irc::error::Error) -> Self {
+        Error::Irc(e)
+    }
+}
+
+impl From<SourceError> for Error {
+    fn from(e: SourceError) -> Self {
+        Error::Source(e)
+    }
+}
+
+impl From<StoreError> for Error {
+    fn from(e: StoreError) -> Self {
+        Error::Store(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
@@ -0,0 +1,99 @@
+use async_trait::async_trait;
+use chrono::DateTime;
+use tracing::debug;
+
+use crate::config::{ApiConfig, ProxyConfig};
+use crate::source::{Attributes, Release, Source, SourceError};
+use crate::web_api::{record_fetch, ApiHandler};
+
+// An RSS 2.0 or Atom announce feed. Items are keyed on their GUID (the entry ID for
+// Atom), the enclosure is the download link and its length the size.
+pub struct RssSource {
+    http: ApiHandler,
+    url: String,
+}
+
+impl RssSource {
+    pub fn new(config: ApiConfig, proxy: Option<&ProxyConfig>) -> Result<Self, SourceError> {
+        let url = config.url.clone();
+        Ok(Self {
+            http: ApiHandler::new(config, proxy)?,
+            url,
+        })
+    }
+
+    async fn fetch_feed(&self) -> Result<Vec<Release>, SourceError> {
+        let body = self.http.fetch_body(&self.url).await?;
+        let releases = parse_feed(&body)?;
+        debug!("Fetched feed with {} items", releases.len());
+        Ok(releases)
+    }
+}
+
+#[async_trait]
+impl Source for RssSource {
+    fn name(&self) -> &str {
+        "RSS"
+    }
+
+    async fn fetch(&self) -> Result<Vec<Release>, SourceError> {
+        let result = self.fetch_feed().await;
+        record_fetch(&result);
+        result
+    }
+}
+
+// Try RSS first, most trackers serve that, then Atom
+fn parse_feed(body: &str) -> Result<Vec<Release>, SourceError> {
+    let rss_error = match body.parse::<rss::Channel>() {
+        Ok(channel) => return Ok(channel.items().iter().filter_map(rss_release).collect()),
+        Err(e) => e,
+    };
+
+    match body.parse::<atom_syndication::Feed>() {
+        Ok(feed) => Ok(feed.entries().iter().map(atom_release).collect()),
+        Err(_) => Err(SourceError::Feed(rss_error.to_string())),
+    }
+}
+
+// Items without a title or without anything to key them on can't be announced
+fn rss_release(item: &rss::Item) -> Option<Release> {
+    let enclosure = item.enclosure();
+    let link = enclosure.map(|enclosure| enclosure.url()).or(item.link())?;
+    let id = item.guid().map_or(link, |guid| guid.value());
+
+    Some(Release {
+        id: id.to_string(),
+        attributes: Attributes {
+            name: item.title()?.to_string(),
+            category: item.categories().first().map(|category| category.name().to_string()),
+            size: enclosure.and_then(|enclosure| enclosure.length().parse().ok()).unwrap_or(0),
+            download_link: link.to_string(),
+            // RFC 2822 in the feed, bumped_at is expected in RFC 3339
+            bumped_at: item.pub_date()
+                .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+                .map(|date| date.to_rfc3339())
+                .unwrap_or_default(),
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
+fn atom_release(entry: &atom_syndication::Entry) -> Release {
+    let enclosure = entry.links().iter().find(|link| link.rel() == "enclosure");
+    let link = enclosure.or(entry.links().first()).map(|link| link.href()).unwrap_or_default();
+
+    Release {
+        id: entry.id().to_string(),
+        attributes: Attributes {
+            name: entry.title().as_str().to_string(),
+            category: entry.categories().first().map(|category| category.label().unwrap_or(category.term()).to_string()),
+            size: enclosure.and_then(|link| link.length()).and_then(|length| length.parse().ok()).unwrap_or(0),
+            download_link: link.to_string(),
+            bumped_at: entry.published().unwrap_or(entry.updated()).to_rfc3339(),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashSet;
+use tracing::debug;
+
+use crate::config::{ChannelFilter, FilterConfig};
+use crate::formatting::size_in_gb;
+use crate::source::{Freeleech, Release};
+
+fn contains_ignore_case(list: &[String], value: &str) -> bool {
+    list.iter().any(|entry| entry.eq_ignore_ascii_case(value))
+}
+
+// Empty lists and unset bounds match everything. Like in the global filter, an item
+// without a resolution counts as "unknown".
+pub fn matches_filter(item: &Release, filter: &ChannelFilter) -> bool {
+    let attributes = &item.attributes;
+    // A missing category or type only matches an empty entry
+    let category = attributes.category.as_deref().unwrap_or_default();
+    let item_type = attributes.r#type.as_deref().unwrap_or_default();
+
+    if !filter.categories.is_empty() && !contains_ignore_case(&filter.categories, category) {
+        return false;
+    }
+
+    if !filter.types.is_empty() && !contains_ignore_case(&filter.types, item_type) {
+        return false;
+    }
+
+    let resolution = attributes.resolution.as_deref().unwrap_or("unknown");
+    if !filter.resolutions.is_empty() && !contains_ignore_case(&filter.resolutions, resolution) {
+        return false;
+    }
+
+    within_size(attributes.size, filter.min_size_gb, filter.max_size_gb)
+}
+
+// An unknown size (0) never satisfies a minimum
+fn within_size(bytes: u64, min_size_gb: Option<f64>, max_size_gb: Option<f64>) -> bool {
+    let size_gb = size_in_gb(bytes);
+
+    if min_size_gb.is_some_and(|min| bytes == 0 || size_gb < min) {
+        return false;
+    }
+    if max_size_gb.is_some_and(|max| size_gb > max) {
+        return false;
+    }
+
+    true
+}
+
+// Global allow/block lists, applied before anything else. Blocklists win over allowlists.
+// An item without a resolution counts as "unknown", so it passes unless
+// allowed_resolutions is set and doesn't list "unknown".
+pub fn passes_filters(item: &Release, filter: &FilterConfig) -> bool {
+    let attributes = &item.attributes;
+    let category = attributes.category.as_deref().unwrap_or_default();
+    let item_type = attributes.r#type.as_deref().unwrap_or_default();
+
+    if contains_ignore_case(&filter.blocked_categories, category) {
+        return false;
+    }
+    if !filter.allowed_categories.is_empty() && !contains_ignore_case(&filter.allowed_categories, category) {
+        return false;
+    }
+
+    if contains_ignore_case(&filter.blocked_types, item_type) {
+        return false;
+    }
+
+    let resolution = attributes.resolution.as_deref().unwrap_or("unknown");
+    if !filter.allowed_resolutions.is_empty() && !contains_ignore_case(&filter.allowed_resolutions, resolution) {
+        return false;
+    }
+
+    if filter.freeleech_only && !attributes.freeleech.is_some_and(Freeleech::is_free) {
+        return false;
+    }
+
+    // An unknown internal flag doesn't count as internal
+    if filter.internal_only && attributes.internal != Some(true) {
+        return false;
+    }
+
+    // Any include pattern is enough, any exclude pattern drops the item
+    let name = &attributes.name;
+    if filter.name_exclude_patterns.iter().any(|pattern| pattern.is_match(name)) {
+        return false;
+    }
+    if !filter.name_include_patterns.is_empty() && !filter.name_include_patterns.iter().any(|pattern| pattern.is_match(name)) {
+        return false;
+    }
+
+    within_size(attributes.size, filter.min_size_gb, filter.max_size_gb)
+}
+
+// Keep the first item of each name, compared lowercased and trimmed. Dropped duplicates
+// aren't marked as seen, they're collapsed the same way again on the next fetch.
+pub fn dedup_by_name(items: Vec<Release>) -> Vec<Release> {
+    let mut names = HashSet::new();
+    items.into_iter()
+        .filter(|item| {
+            let unique = names.insert(item.attributes.name.trim().to_lowercase());
+            if !unique {
+                debug!("⏭️ ID {} duplicates the name {}, skipping", item.id, item.attributes.name);
+            }
+            unique
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(resolution: Option<&str>) -> Release {
+        serde_json::from_value(serde_json::json!({
+            "id": "42",
+            "attributes": {
+                "name": "Some.Release",
+                "resolution": resolution,
+                "download_link": "https://tracker.example/torrent/download/42.abcdef",
+                "bumped_at": "2024-01-01T12:00:00Z",
+            },
+        })).unwrap()
+    }
+
+    #[test]
+    fn missing_resolution_counts_as_unknown_everywhere() {
+        let channel = ChannelFilter { resolutions: vec!["1080p".to_string()], ..ChannelFilter::default() };
+        let global = FilterConfig { allowed_resolutions: channel.resolutions.clone(), ..FilterConfig::default() };
+        assert!(matches_filter(&release(Some("1080P")), &channel));
+        assert!(!matches_filter(&release(None), &channel));
+        assert!(!passes_filters(&release(None), &global));
+
+        let channel = ChannelFilter { resolutions: vec!["1080p".to_string(), "unknown".to_string()], ..channel };
+        let global = FilterConfig { allowed_resolutions: channel.resolutions.clone(), ..global };
+        assert!(matches_filter(&release(None), &channel));
+        assert!(passes_filters(&release(None), &global));
+        assert!(!matches_filter(&release(Some("720p")), &channel));
+    }
+}
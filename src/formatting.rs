@@ -0,0 +1,603 @@
+use chrono::{DateTime, Local, Utc};
+use chrono_tz::Tz;
+
+use crate::config::{AnnounceConfig, SizeUnit};
+use crate::source::Release;
+use crate::web_api::parse_bumped_at;
+
+// Placeholders understood by the announce template
+pub const PLACEHOLDERS: &[&str] = &[
+    "id",
+    "source",
+    "category",
+    "type",
+    "name",
+    "resolution",
+    "freeleech",
+    "internal",
+    "double_upload",
+    "size",
+    "size_gb",
+    "uploader",
+    "url",
+    "bumped_at",
+    "bumped_relative",
+    "bumped_local",
+    "imdb",
+    "tmdb",
+    "magnet",
+];
+
+// Placeholders understood by download_url_template
+pub const DOWNLOAD_URL_PLACEHOLDERS: &[&str] = &["id", "slug", "download_link"];
+
+pub const DEFAULT_TEMPLATE: &str = "Category [{category}] Type [{type}] Name [{name}] Resolution [{resolution}] Freeleech [{freeleech}] Internal [{internal}] Double Upload [{double_upload}] Size [{size_gb} GiB] Uploader [{uploader}] Url [{url}]";
+
+// One release within a batched announcement
+pub const DEFAULT_BATCH_TEMPLATE: &str = "[{category}] {name} {url}";
+const BATCH_SEPARATOR: &str = " | ";
+
+// Convert Bytes to GB
+pub fn size_in_gb(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0 * 1024.0)
+}
+
+pub fn round_to(value: f64, precision: usize) -> f64 {
+    let factor = 10f64.powi(precision as i32);
+    (value * factor).round() / factor
+}
+
+// Human-readable size, e.g. "1.50 GiB". In auto mode the unit is picked after
+// rounding, so 1023.999 MiB becomes 1.00 GiB rather than 1024.00 MiB.
+pub fn format_size(bytes: u64, unit: SizeUnit, precision: usize) -> String {
+    let scaled = |power: i32| round_to(bytes as f64 / 1024f64.powi(power), precision);
+
+    let (value, label) = match unit {
+        SizeUnit::Mb => (scaled(2), "MiB"),
+        SizeUnit::Gb => (scaled(3), "GiB"),
+        SizeUnit::Tb => (scaled(4), "TiB"),
+        SizeUnit::Auto if scaled(2) < 1024.0 => (scaled(2), "MiB"),
+        SizeUnit::Auto if scaled(3) < 1024.0 => (scaled(3), "GiB"),
+        SizeUnit::Auto => (scaled(4), "TiB"),
+    };
+
+    format!("{:.*} {}", precision, value, label)
+}
+
+// Split a template into literal text and placeholder names
+fn tokenize(template: &str) -> Vec<(bool, &str)> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let after = &rest[start + 1..];
+        match after.find('}') {
+            Some(end) if after[..end].chars().all(|c| c.is_ascii_alphanumeric() || c == '_') && end > 0 => {
+                tokens.push((false, &rest[..start]));
+                tokens.push((true, &after[..end]));
+                rest = &after[end + 1..];
+            }
+            _ => {
+                tokens.push((false, &rest[..=start]));
+                rest = after;
+            }
+        }
+    }
+    tokens.push((false, rest));
+
+    tokens
+}
+
+pub fn validate_template(template: &str, placeholders: &[&str]) -> Result<(), String> {
+    for (is_placeholder, name) in tokenize(template) {
+        if is_placeholder && !placeholders.contains(&name) {
+            return Err(format!("unknown placeholder {{{}}}, expected one of: {}", name, placeholders.join(", ")));
+        }
+    }
+    Ok(())
+}
+
+// Render a template, unknown placeholders render as empty
+pub fn render_template<F>(template: &str, lookup: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut rendered = String::with_capacity(template.len());
+    for (is_placeholder, text) in tokenize(template) {
+        if is_placeholder {
+            rendered.push_str(&lookup(text).unwrap_or_default());
+        } else {
+            rendered.push_str(text);
+        }
+    }
+    rendered
+}
+
+// Render an item through the announce template, optionally highlighting
+// the category, resolution and size with mIRC formatting codes
+pub fn format_item(item: &Release, announce: &AnnounceConfig, colors: bool) -> String {
+    render_item(item, &announce.format, announce, colors)
+}
+
+// Several releases as compact segments, packed into as few lines of at most limit
+// bytes as possible. A segment too long for a line on its own gets a line to itself.
+pub fn format_batch(items: &[Release], announce: &AnnounceConfig, colors: bool, limit: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for item in items {
+        let segment = render_item(item, &announce.batch_format, announce, colors);
+        if !line.is_empty() && line.len() + BATCH_SEPARATOR.len() + segment.len() > limit {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push_str(BATCH_SEPARATOR);
+        }
+        line.push_str(&segment);
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+// Add the configured announce_prefix and announce_suffix around a message
+pub fn wrap_announcement(message: &str, announce: &AnnounceConfig) -> String {
+    [announce.announce_prefix.as_str(), message, announce.announce_suffix.as_str()]
+        .iter()
+        .filter(|part| !part.is_empty())
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Bytes the prefix and suffix add to a message
+pub fn wrapping_len(announce: &AnnounceConfig) -> usize {
+    [&announce.announce_prefix, &announce.announce_suffix].iter()
+        .filter(|part| !part.is_empty())
+        .map(|part| part.len() + 1)
+        .sum()
+}
+
+fn render_item(item: &Release, template: &str, announce: &AnnounceConfig, colors: bool) -> String {
+    // Handle optional resolution
+    let resolution = item.attributes.resolution.as_deref().unwrap_or("N/A");
+
+    let category = item.attributes.category.as_deref().unwrap_or("N/A");
+
+    // Determine internal and double upload status
+    let internal_status = yes_no(item.attributes.internal);
+    let du_status = yes_no(item.attributes.double_upload);
+
+    // Convert Bytes to GB
+    let size_in_gb = round_to(size_in_gb(item.attributes.size), announce.size_precision);
+    let size = format_size(item.attributes.size, announce.size_unit, announce.size_precision);
+
+    let download_link = match item.url_template.as_ref().or(announce.download_url_template.as_ref()) {
+        Some(url_template) => render_download_url(item, url_template),
+        None => legacy_download_url(&item.attributes.download_link),
+    };
+
+    // Render the configured template
+    let message = render_template(template, |placeholder| {
+        let value = match placeholder {
+            "id" => item.id.to_string(),
+            "source" => item.source.clone().unwrap_or_default(),
+            "category" if colors => bold(category),
+            "category" => category.to_string(),
+            "type" => item.attributes.r#type.as_deref().unwrap_or("N/A").to_string(),
+            "name" => item.attributes.name.to_string(),
+            "resolution" if colors => colored(resolution_color(resolution), resolution),
+            "resolution" => resolution.to_string(),
+            "freeleech" => item.attributes.freeleech.map_or("N/A".to_string(), |freeleech| freeleech.to_string()),
+            "internal" => internal_status.to_string(),
+            "double_upload" => du_status.to_string(),
+            "size" if colors => colored(size_color(size_in_gb), &size),
+            "size" => size.clone(),
+            "size_gb" if colors => colored(size_color(size_in_gb), &size_in_gb.to_string()),
+            "size_gb" => size_in_gb.to_string(),
+            "uploader" => item.attributes.uploader.as_deref().unwrap_or("N/A").to_string(),
+            "url" => download_link.to_string(),
+            "bumped_at" => item.attributes.bumped_at.to_string(),
+            // Empty when bumped_at can't be parsed
+            "bumped_relative" => parse_bumped_at(&item.attributes.bumped_at)
+                .map(|bumped_at| relative_time(bumped_at, Utc::now()))
+                .unwrap_or_default(),
+            "bumped_local" => parse_bumped_at(&item.attributes.bumped_at)
+                .map(|bumped_at| local_time(bumped_at, announce))
+                .unwrap_or_default(),
+            // Empty rather than N/A when the API doesn't know the ID
+            "imdb" => imdb_url(item).unwrap_or_default(),
+            "tmdb" => tmdb_url(item).unwrap_or_default(),
+            "magnet" => magnet_uri(item).unwrap_or_default(),
+            _ => return None,
+        };
+        Some(value)
+    });
+
+    // Tell re-announced bumps apart from fresh releases
+    if item.bumped && !announce.bumped_prefix.is_empty() {
+        format!("{} {}", announce.bumped_prefix, message)
+    } else {
+        message
+    }
+}
+
+fn yes_no(value: Option<bool>) -> &'static str {
+    match value {
+        Some(true) => "Yes",
+        Some(false) => "No",
+        None => "N/A",
+    }
+}
+
+// Largest whole unit only, e.g. "2m ago". Timestamps slightly in the future
+// (clock skew between us and the tracker) count as just now.
+fn relative_time(then: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let secs = (now - then).num_seconds();
+    match secs {
+        ..=0 => "just now".to_string(),
+        1..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+// The timezone and format are validated at startup
+fn local_time(time: DateTime<Utc>, announce: &AnnounceConfig) -> String {
+    match announce.timezone.as_deref().and_then(|name| name.parse::<Tz>().ok()) {
+        Some(timezone) => time.with_timezone(&timezone).format(&announce.time_format).to_string(),
+        None => time.with_timezone(&Local).format(&announce.time_format).to_string(),
+    }
+}
+
+// The download link with the file name's extension (usually the passkey) removed,
+// e.g. https://tracker/torrent/download/123.abcdef -> 123
+fn download_slug(download_link: &str) -> &str {
+    let file_name = download_link.rsplit('/').next().unwrap_or(download_link);
+    file_name.split_once('.').map_or(file_name, |(slug, _)| slug)
+}
+
+fn render_download_url(item: &Release, url_template: &str) -> String {
+    render_template(url_template, |placeholder| {
+        let value = match placeholder {
+            "id" => item.id.to_string(),
+            "slug" => download_slug(&item.attributes.download_link).to_string(),
+            "download_link" => item.attributes.download_link.to_string(),
+            _ => return None,
+        };
+        Some(value)
+    })
+}
+
+// UNIT3D's download link turned into the torrent page: torrent -> torrents and the
+// passkey suffix dropped. Only a dot in the last path segment is cut, so hostnames
+// and links without a suffix are left alone.
+fn legacy_download_url(download_link: &str) -> String {
+    let download_link = download_link.replace("torrent", "torrents");
+    match download_link.rsplit_once('.') {
+        Some((page, _)) if !download_link[page.len()..].contains('/') => page.to_string(),
+        _ => download_link,
+    }
+}
+
+// IMDb IDs come with or without the tt prefix, and without zero padding from some trackers
+fn imdb_url(item: &Release) -> Option<String> {
+    let id = item.attributes.imdb_id.as_deref()?;
+    let digits = id.trim_start_matches("tt");
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(format!("https://www.imdb.com/title/tt{:0>7}/", digits))
+}
+
+// TMDb splits movies and TV shows, anything in a TV category links to the show
+fn tmdb_url(item: &Release) -> Option<String> {
+    let id = item.attributes.tmdb_id.as_deref()?;
+    let is_tv = item.attributes.category.as_deref().is_some_and(|category| category.to_ascii_lowercase().contains("tv"));
+    let kind = if is_tv { "tv" } else { "movie" };
+    Some(format!("https://www.themoviedb.org/{}/{}", kind, id))
+}
+
+fn magnet_uri(item: &Release) -> Option<String> {
+    let info_hash = item.attributes.info_hash.as_deref()?;
+    Some(format!("magnet:?xt=urn:btih:{}&dn={}", info_hash, url_encode(&item.attributes.name)))
+}
+
+fn url_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// mIRC formatting control characters
+const BOLD: char = '\x02';
+const COLOR: char = '\x03';
+const HEX_COLOR: char = '\x04';
+const RESET: char = '\x0F';
+const MONOSPACE: char = '\x11';
+const REVERSE: char = '\x16';
+const ITALIC: char = '\x1D';
+const STRIKETHROUGH: char = '\x1E';
+const UNDERLINE: char = '\x1F';
+
+// Split a message into lines of at most `limit` bytes, breaking between "[...] " fields so
+// a field like the Url is never cut in half. Only a single field longer than a whole line is
+// cut, at the last UTF-8 character boundary that fits.
+pub fn split_message(message: &str, limit: usize) -> Vec<String> {
+    if message.len() <= limit {
+        return vec![message.to_string()];
+    }
+
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for mut field in message.split_inclusive("] ") {
+        if !line.is_empty() && line.len() + field.trim_end().len() > limit {
+            lines.push(line.trim_end().to_string());
+            line.clear();
+        }
+
+        while field.trim_end().len() > limit {
+            let cut = floor_char_boundary(field, limit);
+            lines.push(field[..cut].to_string());
+            field = &field[cut..];
+        }
+        line.push_str(field);
+    }
+
+    if !line.trim_end().is_empty() {
+        lines.push(line.trim_end().to_string());
+    }
+    lines
+}
+
+// Largest index <= max on a character boundary, but always past the first character
+fn floor_char_boundary(text: &str, max: usize) -> usize {
+    let mut index = max.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    if index == 0 {
+        index = text.chars().next().map_or(0, char::len_utf8);
+    }
+    index
+}
+
+// mIRC color numbers used when colors are enabled
+const GREEN: u8 = 3;
+const RED: u8 = 4;
+const PURPLE: u8 = 6;
+const ORANGE: u8 = 7;
+const CYAN: u8 = 10;
+const LIGHT_BLUE: u8 = 12;
+const GREY: u8 = 14;
+
+// Sizes from here on are shown in orange, and from the second threshold on in red
+const SIZE_MEDIUM_GB: f64 = 10.0;
+const SIZE_LARGE_GB: f64 = 50.0;
+
+fn bold(text: &str) -> String {
+    format!("{}{}{}", BOLD, text, BOLD)
+}
+
+// Always two digits, otherwise a value starting with a digit would be read as part of the color
+fn colored(color: u8, text: &str) -> String {
+    format!("{}{:02}{}{}", COLOR, color, text, COLOR)
+}
+
+fn resolution_color(resolution: &str) -> u8 {
+    match resolution.to_ascii_lowercase().as_str() {
+        "4320p" | "2160p" => PURPLE,
+        "1080p" | "1080i" => LIGHT_BLUE,
+        "720p" => CYAN,
+        _ => GREY,
+    }
+}
+
+fn size_color(size_gb: f64) -> u8 {
+    if size_gb >= SIZE_LARGE_GB {
+        RED
+    } else if size_gb >= SIZE_MEDIUM_GB {
+        ORANGE
+    } else {
+        GREEN
+    }
+}
+
+// Remove all color and formatting codes, for channels that are +c (no colors)
+pub fn strip_control_codes(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            // \x03 is followed by up to two digits, optionally ",<up to two digits>"
+            COLOR => {
+                skip_digits(&mut chars, 2, |c| c.is_ascii_digit());
+            }
+            // \x04 is followed by a six digit hex color, optionally ",<hex color>"
+            HEX_COLOR => {
+                skip_digits(&mut chars, 6, |c| c.is_ascii_hexdigit());
+            }
+            BOLD | RESET | MONOSPACE | REVERSE | ITALIC | STRIKETHROUGH | UNDERLINE => {}
+            _ => stripped.push(c),
+        }
+    }
+
+    stripped
+}
+
+fn skip_digits<I, F>(chars: &mut std::iter::Peekable<I>, max: usize, is_digit: F)
+where
+    I: Iterator<Item = char> + Clone,
+    F: Fn(char) -> bool,
+{
+    let mut skipped = 0;
+    while skipped < max && chars.peek().is_some_and(|c| is_digit(*c)) {
+        chars.next();
+        skipped += 1;
+    }
+
+    // Only consume the comma when a background color actually follows it
+    if skipped > 0 && chars.peek() == Some(&',') {
+        let mut lookahead = chars.clone();
+        lookahead.next();
+        if lookahead.peek().is_some_and(|c| is_digit(*c)) {
+            chars.next();
+            let mut skipped = 0;
+            while skipped < max && chars.peek().is_some_and(|c| is_digit(*c)) {
+                chars.next();
+                skipped += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A release with only the fields every source must report
+    fn bare_release() -> Release {
+        serde_json::from_value(serde_json::json!({
+            "id": "42",
+            "attributes": {
+                "name": "Some.Release.1080p",
+                "download_link": "https://tracker.example/torrent/download/42.abcdef",
+                "bumped_at": "2024-01-01T12:00:00Z",
+            },
+        })).unwrap()
+    }
+
+    fn announce(format: &str) -> AnnounceConfig {
+        AnnounceConfig { format: format.to_string(), ..AnnounceConfig::default() }
+    }
+
+    #[test]
+    fn missing_fields_render_as_not_available() {
+        let message = format_item(&bare_release(), &AnnounceConfig::default(), false);
+        assert_eq!(message, "Category [N/A] Type [N/A] Name [Some.Release.1080p] Resolution [N/A] Freeleech [N/A] \
+            Internal [N/A] Double Upload [N/A] Size [0 GiB] Uploader [N/A] Url [https://tracker.example/torrents/download/42]");
+    }
+
+    #[test]
+    fn unexpected_flag_values_render_as_not_available() {
+        let mut release: Release = serde_json::from_value(serde_json::json!({
+            "id": "42",
+            "attributes": {
+                "name": "Some.Release.1080p",
+                "download_link": "https://tracker.example/torrent/download/42.abcdef",
+                "bumped_at": "2024-01-01T12:00:00Z",
+                "internal": 2,
+            },
+        })).unwrap();
+        assert_eq!(format_item(&release, &announce("Internal [{internal}]"), false), "Internal [N/A]");
+        release.attributes.internal = Some(true);
+        assert_eq!(format_item(&release, &announce("Internal [{internal}]"), false), "Internal [Yes]");
+    }
+
+    #[test]
+    fn download_link_without_a_dot_is_kept() {
+        let mut release = bare_release();
+        release.attributes.download_link = "https://tracker/torrent/download/42".to_string();
+        assert_eq!(format_item(&release, &announce("{url}"), false), "https://tracker/torrents/download/42");
+    }
+
+    #[test]
+    fn missing_external_ids_render_empty() {
+        let message = format_item(&bare_release(), &announce("{name} <{imdb}> <{tmdb}> <{magnet}> <{source}>"), false);
+        assert_eq!(message, "Some.Release.1080p <> <> <> <>");
+    }
+
+    #[test]
+    fn unknown_placeholders_render_empty() {
+        assert_eq!(format_item(&bare_release(), &announce("{id}:{nope}:{id}"), false), "42::42");
+    }
+
+    #[test]
+    fn braces_that_are_no_placeholder_stay_literal() {
+        let message = format_item(&bare_release(), &announce("{} {not one} {id"), false);
+        assert_eq!(message, "{} {not one} {id");
+    }
+
+    #[test]
+    fn unknown_placeholders_fail_validation() {
+        assert!(validate_template("{name} {url}", PLACEHOLDERS).is_ok());
+        let e = validate_template("{name} {nope}", PLACEHOLDERS).unwrap_err();
+        assert!(e.starts_with("unknown placeholder {nope}"), "{}", e);
+        assert!(validate_template("{slug}", PLACEHOLDERS).is_err());
+    }
+
+    #[test]
+    fn empty_template_renders_empty() {
+        assert!(validate_template("", PLACEHOLDERS).is_ok());
+        assert_eq!(format_item(&bare_release(), &announce(""), false), "");
+        assert_eq!(format_item(&bare_release(), &announce(""), true), "");
+    }
+
+    #[test]
+    fn split_cuts_long_fields_on_character_boundaries() {
+        // 2, 3 and 4 byte characters, none of which fit the limit evenly
+        for (text, limit) in [("ééééé", 3), ("€€€€", 5), ("🎬🎬🎬", 6), ("aé€🎬aé€🎬", 4)] {
+            let lines = split_message(text, limit);
+            assert!(lines.len() > 1, "{:?}", lines);
+            assert!(lines.iter().all(|line| line.len() <= limit), "{:?}", lines);
+            assert_eq!(lines.concat(), text);
+        }
+    }
+
+    #[test]
+    fn split_cuts_at_least_one_character_below_its_width() {
+        assert_eq!(split_message("🎬🎬", 2), ["🎬", "🎬"]);
+    }
+
+    #[test]
+    fn split_keeps_multi_byte_fields_whole() {
+        // 19 characters but 22 bytes, the limit counts bytes
+        assert_eq!(split_message("[Café Société] [ab]", 20), ["[Café Société]", "[ab]"]);
+    }
+
+    #[test]
+    fn split_leaves_a_message_within_the_limit_alone() {
+        // 12 bytes, only 8 characters
+        assert_eq!(split_message("€€€€ abc", 16), ["€€€€ abc"]);
+        assert_eq!(split_message("€€€€", 12), ["€€€€"]);
+    }
+
+    const MIB: u64 = 1024 * 1024;
+    const GIB: u64 = 1024 * MIB;
+    const TIB: u64 = 1024 * GIB;
+
+    #[test]
+    fn auto_size_switches_units_at_whole_units() {
+        assert_eq!(format_size(0, SizeUnit::Auto, 2), "0.00 MiB");
+        assert_eq!(format_size(1023 * MIB, SizeUnit::Auto, 2), "1023.00 MiB");
+        assert_eq!(format_size(GIB, SizeUnit::Auto, 2), "1.00 GiB");
+        assert_eq!(format_size(1023 * GIB, SizeUnit::Auto, 2), "1023.00 GiB");
+        assert_eq!(format_size(TIB, SizeUnit::Auto, 2), "1.00 TiB");
+    }
+
+    #[test]
+    fn auto_size_picks_the_unit_after_rounding() {
+        assert_eq!(format_size(GIB - 1, SizeUnit::Auto, 2), "1.00 GiB");
+        assert_eq!(format_size(TIB - 1, SizeUnit::Auto, 2), "1.00 TiB");
+        // 1023.6 MiB only rounds up to a whole GiB without decimals
+        assert_eq!(format_size(1_073_322_394, SizeUnit::Auto, 1), "1023.6 MiB");
+        assert_eq!(format_size(1_073_322_394, SizeUnit::Auto, 0), "1 GiB");
+    }
+
+    #[test]
+    fn fixed_size_unit_ignores_boundaries() {
+        assert_eq!(format_size(TIB, SizeUnit::Mb, 2), "1048576.00 MiB");
+        assert_eq!(format_size(MIB, SizeUnit::Gb, 3), "0.001 GiB");
+        assert_eq!(format_size(GIB, SizeUnit::Tb, 2), "0.00 TiB");
+    }
+}
@@ -0,0 +1,113 @@
+// Liveness/readiness endpoint for container orchestrators.
+//
+// `GET /health` answers 200 while the bot is connected to IRC and the API was
+// fetched successfully within the allowed age, and 503 otherwise. Nothing is fetched
+// during quiet hours or while paused or muted, then the fetch age isn't checked.
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::time::{Duration, Instant};
+use tracing::info;
+
+use crate::http::{self, Response};
+
+pub struct HealthState {
+    connected: AtomicBool,
+    // Last successful API fetch, or startup until the first one
+    last_fetch: Mutex<Instant>,
+    max_fetch_age: Duration,
+    // Fetching is held back on purpose
+    idle: AtomicBool,
+}
+
+impl HealthState {
+    pub fn new(max_fetch_age: Duration) -> Self {
+        Self {
+            connected: AtomicBool::new(false),
+            last_fetch: Mutex::new(Instant::now()),
+            max_fetch_age,
+            idle: AtomicBool::new(false),
+        }
+    }
+
+    pub fn set_connected(&self, connected: bool) {
+        self.connected.store(connected, Ordering::Relaxed);
+    }
+
+    pub fn fetched(&self) {
+        *self.last_fetch.lock().unwrap() = Instant::now();
+    }
+
+    // Once fetching resumes the sources get a full max_fetch_age to answer again
+    pub fn set_idle(&self, idle: bool) {
+        if self.idle.swap(idle, Ordering::Relaxed) && !idle {
+            self.fetched();
+        }
+    }
+
+    fn check(&self) -> Result<String, String> {
+        if !self.connected.load(Ordering::Relaxed) {
+            return Err("not connected to IRC".to_string());
+        }
+
+        let age = self.last_fetch.lock().unwrap().elapsed();
+        if age > self.max_fetch_age && !self.idle.load(Ordering::Relaxed) {
+            return Err(format!("no successful API fetch for {}s", age.as_secs()));
+        }
+
+        Ok("ok".to_string())
+    }
+}
+
+// Bind the health endpoint and serve it from a separate task
+pub async fn spawn(addr: &str, state: Arc<HealthState>) -> io::Result<()> {
+    http::serve(addr, move |path| match path {
+        "/health" => match state.check() {
+            Ok(body) => Response::ok("text/plain", body),
+            Err(reason) => Response::unavailable(reason),
+        },
+        _ => Response::not_found(),
+    }).await?;
+    info!("🩺 Health check listening on http://{}/health", addr);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn stale_fetch_is_unhealthy() {
+        let state = HealthState::new(Duration::from_secs(60));
+        assert!(state.check().is_err());
+        state.set_connected(true);
+        assert!(state.check().is_ok());
+
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert!(state.check().is_err());
+        state.fetched();
+        assert!(state.check().is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn held_announcements_stay_healthy() {
+        let state = HealthState::new(Duration::from_secs(60));
+        state.set_connected(true);
+        state.set_idle(true);
+        tokio::time::advance(Duration::from_secs(8 * 3600)).await;
+        assert!(state.check().is_ok());
+
+        // Resuming restarts the clock rather than failing right away
+        state.set_idle(false);
+        assert!(state.check().is_ok());
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert!(state.check().is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn held_announcements_still_need_irc() {
+        let state = HealthState::new(Duration::from_secs(60));
+        state.set_idle(true);
+        assert!(state.check().is_err());
+    }
+}
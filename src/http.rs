@@ -0,0 +1,90 @@
+// Minimal HTTP/1.1 server for the metrics and health endpoints. Only GET request
+// lines are looked at, every response closes the connection.
+use std::io;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, Duration};
+use tracing::{debug, error};
+
+// Pause after a failed accept, so a persistent error (e.g. out of file descriptors) doesn't spin
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+pub struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    pub fn ok(content_type: &'static str, body: String) -> Self {
+        Self { status: "200 OK", content_type, body }
+    }
+
+    pub fn unavailable(body: String) -> Self {
+        Self { status: "503 Service Unavailable", content_type: "text/plain", body }
+    }
+
+    pub fn not_found() -> Self {
+        Self { status: "404 Not Found", content_type: "text/plain", body: String::new() }
+    }
+
+    fn to_http(&self) -> String {
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            self.content_type,
+            self.body.len(),
+            self.body,
+        )
+    }
+}
+
+// Bind addr and answer GET requests from a separate task, `route` maps the path to a response
+pub async fn serve<F>(addr: &str, route: F) -> io::Result<()>
+where
+    F: Fn(&str) -> Response + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(addr).await?;
+    let route = Arc::new(route);
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle_connection(stream, Arc::clone(&route)));
+                }
+                Err(e) => {
+                    error!("Failed to accept HTTP connection: {}", e);
+                    sleep(ACCEPT_RETRY_DELAY).await;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+async fn handle_connection<F>(mut stream: TcpStream, route: Arc<F>)
+where
+    F: Fn(&str) -> Response,
+{
+    // Only the request line matters, anything beyond the first read is ignored
+    let mut buffer = [0; 1024];
+    let read = match stream.read(&mut buffer).await {
+        Ok(read) => read,
+        Err(e) => {
+            debug!("Failed to read HTTP request: {}", e);
+            return;
+        }
+    };
+    let request = String::from_utf8_lossy(&buffer[..read]);
+    let mut request_line = request.lines().next().unwrap_or_default().split_whitespace();
+
+    let response = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some(path)) => route(path),
+        _ => Response::not_found(),
+    };
+
+    let _ = stream.write_all(response.to_http().as_bytes()).await;
+}
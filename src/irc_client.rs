@@ -1,281 +1,1246 @@
 use irc::client::prelude::*;
+use irc::client::data::AccessLevel;
+use irc::proto::CapSubCommand;
 use tokio_stream::StreamExt as _;
-use tracing::{debug, info, warn, error};
-use std::fs::{self, OpenOptions};
-use std::io::{BufWriter, Write};
-use std::path::Path;
-use serde::{Serialize, Deserialize};
-use std::collections::HashSet;
-use std::error::Error;
+use tracing::{debug, error, info, trace, warn};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{interval, sleep, sleep_until, Duration, Instant};
+use base64::Engine as _;
 
-use crate::config::IrcConfig;
-use crate::web_api::ApiItem;
+use crate::budget::TokenBucket;
+use crate::commands::{help, matches_hostmask, matches_mask, parse_command, BotCommand};
+use crate::config::{AnnounceConfig, AppConfig, IrcConfig, ProxyConfig, Secret};
+use crate::control::{ControlCommand, ControlRequest};
+use crate::filter::matches_filter;
+use crate::formatting::{format_batch, format_item, split_message, strip_control_codes, wrap_announcement, wrapping_len};
+use crate::metrics::{Metrics, METRICS};
+use crate::notifier::{Notifier, NotifyError};
+use crate::source::Release;
 
-#[derive(Debug, Serialize, Deserialize, Clone, Hash, Eq, PartialEq)]
-struct SeenItem {
-    id: String,
-    bumped_at: String,
+// How long to wait for the PONG answering a connection check
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+// How long a successful connection check vouches for the announcements sent after it
+const LIVENESS_WINDOW: Duration = Duration::from_secs(30);
+// Maximum length of an IRC line, including the trailing CRLF
+const MAX_LINE_LEN: usize = 512;
+// Services nick identified to, only its notices count as the identification result
+const NICKSERV: &str = "NickServ";
+// NickServ notices that mean identification will not succeed
+const NS_FAILURE_PATTERNS: &[&str] = &["Invalid password", "Password incorrect", "Access denied"];
+// Server ERROR reasons (lowercased) that reconnecting right away won't fix
+const BAN_PATTERNS: &[&str] = &["k-lined", "g-lined", "z-lined", "banned", "killed"];
+// Wait before reconnecting after a server ERROR, doubled for each one within the window
+const SERVER_ERROR_DELAY: Duration = Duration::from_secs(5);
+const MAX_SERVER_ERROR_DELAY: Duration = Duration::from_secs(300);
+const SERVER_ERROR_WINDOW: Duration = Duration::from_secs(3600);
+
+// irc's proxy error only says "a proxy error occurred", include what the proxy reported
+pub fn describe_error(e: &irc::error::Error) -> String {
+    match e {
+        irc::error::Error::Proxy(source) => format!("proxy error: {}", source),
+        _ => e.to_string(),
+    }
 }
 
 pub struct IrcClient {
     pub client: Client,
     pub config: IrcConfig,
+    announce: AnnounceConfig,
+    proxy: Option<ProxyConfig>,
     pub stream: irc::client::ClientStream,
-    seen_ids: Arc<Mutex<HashSet<SeenItem>>>,
-    announced_file: String,
+    channel_modes: HashMap<String, HashSet<char>>,
+    recent: VecDeque<String>,
+    retry_queue: VecDeque<(Release, Vec<String>)>,
+    // max_announcements_per_minute and the items held back by it, none of them marked as seen yet
+    announce_rate: Option<TokenBucket>,
+    held: VecDeque<Release>,
+    last_send: Option<Instant>,
+    // Last successful PING round trip on this connection
+    last_verified: Option<Instant>,
+    // Rejoin attempts per channel on this connection
+    rejoin_attempts: HashMap<String, u32>,
+    ping_seq: u64,
+    // For !stats
+    started: Instant,
+    last_fetch: Option<Instant>,
+    // Cleared while announcements are paused, shared with the main loop
+    announcing: Arc<AtomicBool>,
+    // Admin commands are handed to the main loop like control socket commands
+    control: Option<mpsc::Sender<ControlRequest>>,
+    // Log announcements instead of sending them
+    dry_run: bool,
+    // OPER sent, waiting for the server's answer
+    oper_pending: bool,
+    // Set when the server sent ERROR, how long to wait before reconnecting
+    closing: Option<Duration>,
+    server_errors: u32,
+    last_server_error: Option<Instant>,
 }
 
 impl IrcClient {
-    pub async fn new(config: IrcConfig, announced_file: String) -> irc::error::Result<Self> {
-        let irc_config = Config {
+    pub async fn new(config: IrcConfig, announce: AnnounceConfig, app: AppConfig) -> irc::error::Result<Self> {
+        let proxy = app.proxy.as_ref().and_then(|proxy| ProxyConfig::parse(proxy.expose()).ok());
+        let (client, stream) = Self::build_client(&config, proxy.as_ref()).await?;
+        let announce_rate = config.max_announcements_per_minute.map(|max| TokenBucket::new(max, Duration::from_secs(60)));
+
+        Ok(Self {
+            client,
+            stream,
+            config,
+            announce,
+            proxy,
+            channel_modes: HashMap::new(),
+            recent: VecDeque::new(),
+            retry_queue: VecDeque::new(),
+            announce_rate,
+            held: VecDeque::new(),
+            last_send: None,
+            last_verified: None,
+            rejoin_attempts: HashMap::new(),
+            ping_seq: 0,
+            started: Instant::now(),
+            last_fetch: None,
+            announcing: Arc::new(AtomicBool::new(true)),
+            control: None,
+            dry_run: app.dry_run,
+            oper_pending: false,
+            closing: None,
+            server_errors: 0,
+            last_server_error: None,
+        })
+    }
+
+    async fn build_client(config: &IrcConfig, proxy: Option<&ProxyConfig>) -> irc::error::Result<(Client, irc::client::ClientStream)> {
+        let mut irc_config = Config {
             nickname: Some(config.nickname.to_string()),
-            password: Some(config.password.to_string()),
+            username: Some(config.username.as_deref().unwrap_or(&config.nickname).to_string()),
+            realname: Some(config.realname.as_deref().unwrap_or(&config.nickname).to_string()),
+            password: Some(config.server_password()),
             server: Some(config.server.to_owned()),
             port: Some(config.port),
             use_tls: Some(config.use_tls),
-            channels: vec![config.channel.to_string()],
+            cert_path: config.ca_cert.clone(),
+            dangerously_accept_invalid_certs: Some(config.insecure_tls),
+            alt_nicks: config.alt_nicks.clone(),
+            // The irc crate answers CTCP VERSION, PING and TIME by itself
+            version: Some(config.ctcp_version.clone()
+                .unwrap_or_else(|| format!("announcarr {}", env!("CARGO_PKG_VERSION")))),
+            channels: Self::all_channels(config),
+            channel_keys: Self::all_channels(config).into_iter()
+                .filter_map(|channel| Self::channel_key(config, &channel).map(|key| (channel, key.expose().to_string())))
+                .collect(),
             ..Config::default()
         };
-
-        let seen_ids = match Self::load_seen_ids(&announced_file) {
-            Ok(ids) => ids,
-            Err(e) => {
-                error!("Failed to load seen IDs: {}", e);
-                HashSet::new()
-            }
-        };
+        if config.use_tls && config.insecure_tls {
+            warn!("⚠️ TLS certificate verification is DISABLED for IRC, never use insecure_tls in production");
+        }
+        if let Some(proxy) = proxy {
+            irc_config.proxy_type = Some(irc::client::data::ProxyType::Socks5);
+            irc_config.proxy_server = Some(proxy.host.to_string());
+            irc_config.proxy_port = Some(proxy.port);
+            irc_config.proxy_username = proxy.username.clone();
+            irc_config.proxy_password = proxy.password.as_ref().map(|password| password.expose().to_string());
+        }
 
         let mut client = Client::from_config(irc_config).await?;
         let stream = client.stream()?;
+        Ok((client, stream))
+    }
 
-        Ok(Self {
-            client,
-            stream,
-            config,
-            seen_ids: Arc::new(Mutex::new(seen_ids)),
-            announced_file,
-        })
+    // Replace the connection with a fresh one, the seen IDs are kept as-is
+    pub async fn reconnect(&mut self) -> irc::error::Result<()> {
+        info!("🔌 Reconnecting to {}:{} ...", self.config.server, self.config.port);
+        let (client, stream) = Self::build_client(&self.config, self.proxy.as_ref()).await?;
+        self.client = client;
+        self.stream = stream;
+        self.channel_modes.clear();
+        self.rejoin_attempts.clear();
+        self.last_verified = None;
+        self.oper_pending = false;
+
+        self.connect().await?;
+        Metrics::inc(&METRICS.reconnects);
+        Ok(())
     }
 
     pub async fn connect(&mut self) -> irc::error::Result<()> {
-        self.client.identify()?;
+        // Behind a bouncer, services auth is the bouncer's job
+        let bouncer = self.config.bouncer.is_some();
+        if self.config.sasl && !bouncer {
+            self.register_with_sasl().await?;
+        } else {
+            self.client.identify()?;
+        }
 
         // Wait for successful registration (001 RPL_WELCOME) until nickserv auth
         info!("⏳ Waiting for server registration...");
-        while let Some(message) = self.stream.next().await {
-            let message = message?;
+        loop {
+            let message = self.next_message().await?;
 
             // Check for successful registration
-            if let Command::Response(Response::RPL_WELCOME, _) = message.command {
-                info!("✅ Registered with server");
+            if let Command::Response(Response::RPL_WELCOME, args) = &message.command {
+                let registered_nick = args.first().map_or("", |nick| nick.as_str());
+                info!("✅ Registered with server as {}", registered_nick);
+                if !registered_nick.eq_ignore_ascii_case(&self.config.nickname) && !bouncer {
+                    self.regain_nick()?;
+                }
                 break;
             }
 
-            // Also respond to PING during registration
-            if let Command::PING(server, _) = &message.command {
-                self.client.send_pong(server)?;
+            if let Command::Response(Response::ERR_NICKNAMEINUSE, _) = &message.command {
+                warn!("⚠️ Nickname in use, trying the next alternate");
             }
         }
 
-        info!("🪪  NickServ identifying as {} ...", self.config.nickname);
-        self.client.send_privmsg("NickServ", format!("IDENTIFY {} {}", self.config.nickname, self.config.ns_password))?;
-        // Wait for the NickServ confirmation message
-        info!("⏳ Waiting for NickServ confirmation...");
-        while let Some(message) = self.stream.next().await {
-            let message = message?;
+        if bouncer {
+            info!("🔗 Connected through a bouncer, skipping NickServ identification");
+        } else if !self.config.sasl {
+            info!("🪪  NickServ identifying as {} ...", self.config.nickname);
+            self.client.send_privmsg(NICKSERV, format!("IDENTIFY {} {}", self.config.nickname, self.config.ns_password.expose()))?;
+            // Wait for the NickServ confirmation message
+            info!("⏳ Waiting for NickServ confirmation...");
+            self.wait_for_identification(Duration::from_secs(self.config.ns_grace_secs)).await?;
+        }
 
-            if let Command::NOTICE(_target, content) = message.command {
-                if content.contains("Password accepted") {
-                    info!("✅ NickServ identification successful");
-                    break;
-                }
+        let mut pending = Self::all_channels(&self.config);
+        if self.config.chanserv_invite {
+            for channel in &pending {
+                info!("✉️ Asking ChanServ for an invite to {} ...", channel);
+                self.client.send_privmsg("ChanServ", format!("INVITE {}", channel))?;
             }
         }
+        for channel in &pending {
+            info!("⏳ Joining {} ...", channel);
+            self.join(channel)?;
+        }
 
-        info!("⏳ Joining {} ...", self.config.channel);
-        self.client.send_join(self.config.channel.to_string())?;
-
-        while let Some(message) = self.stream.next().await {
-            let message = message?;
+        loop {
+            let message = self.next_message().await?;
 
-            if let Command::Response(_, ref text) = &message.command {
-                if text.contains(&String::from("End of /NAMES list.")) {
-                    info!("✅ Channel {} joined", self.config.channel);
+            self.handle_incoming(&message);
 
-                    // Now that we're fully connected, try OPER if needed
-                    if let Some(true) = &self.config.oper {
-                        info!("⏳ Attempting to gain operator privileges...");
-                        self.client.send_oper(&self.config.nickname, &self.config.password)?;
+            match &message.command {
+                Command::Response(Response::RPL_ENDOFNAMES, args) => {
+                    if let Some(channel) = args.get(1) {
+                        info!("✅ Channel {} joined", channel);
+                        // Ask for the channel modes (324 RPL_CHANNELMODEIS) so +c can be respected
+                        self.client.send(Command::ChannelMODE(channel.to_string(), vec![]))?;
+                        pending.retain(|c| !c.eq_ignore_ascii_case(channel));
                     }
+                }
+                // Don't wait forever for channels that refused us
+                Command::Response(
+                    response @ (Response::ERR_BADCHANNELKEY | Response::ERR_BANNEDFROMCHAN
+                        | Response::ERR_INVITEONLYCHAN | Response::ERR_CHANNELISFULL),
+                    args,
+                ) => {
+                    if let Some(channel) = args.get(1) {
+                        if *response == Response::ERR_BADCHANNELKEY {
+                            error!("❌ Cannot join {}: wrong or missing channel key", channel);
+                        } else if *response == Response::ERR_INVITEONLYCHAN {
+                            error!("❌ Cannot join {}: channel is invite-only (+i), will join once invited", channel);
+                        } else {
+                            error!("❌ Cannot join {}: {}", channel, args.last().map_or("", |reason| reason.as_str()));
+                        }
+                        pending.retain(|c| !c.eq_ignore_ascii_case(channel));
+                    }
+                }
+                _ => continue,
+            }
 
-                    return Ok(());
+            if pending.is_empty() {
+                // Now that we're fully connected, try OPER if needed
+                if let Some(true) = &self.config.oper {
+                    self.send_oper();
                 }
+
+                return Ok(());
             }
         }
-        Ok(())
     }
 
-    pub async fn verify_connected(&mut self) -> bool {
-        debug!("Performing IRC connection check (Pong) ...");
+    // The next message from the server, a closed connection is an error rather than
+    // the end of the stream so nothing mistakes it for a finished handshake
+    async fn next_message(&mut self) -> irc::error::Result<Message> {
+        match self.stream.next().await {
+            Some(message) => message,
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "connection closed by the server",
+            ).into()),
+        }
+    }
 
-        // Verify with a WHOIS/PING
-        match self.client.send_pong(&self.config.nickname) {
-            Ok(_) => {
-                debug!("✅ IRC connection ok");
-                true
+    pub fn handle_incoming(&mut self, message: &Message) {
+        trace!("IRC <- {}", message.to_string().trim_end());
+
+        match &message.command {
+            Command::Response(Response::RPL_CHANNELMODEIS, args) => {
+                if let (Some(channel), Some(modes)) = (args.get(1), args.get(2)) {
+                    debug!("Channel {} has modes {}", channel, modes);
+                    let modes = modes.chars().filter(|c| *c != '+').collect();
+                    self.channel_modes.insert(channel.to_lowercase(), modes);
+                }
+            }
+            Command::ChannelMODE(channel, modes) => {
+                let tracked = self.channel_modes.entry(channel.to_lowercase()).or_default();
+                for mode in modes {
+                    match mode {
+                        Mode::Plus(mode, _) => {
+                            tracked.extend(mode.to_string().chars());
+                        }
+                        Mode::Minus(mode, _) => {
+                            for c in mode.to_string().chars() {
+                                tracked.remove(&c);
+                            }
+                        }
+                        Mode::NoPrefix(_) => {}
+                    }
+                }
+            }
+            Command::PRIVMSG(target, body) if target.starts_with(['#', '&']) => {
+                if let Some(command) = parse_command(body) {
+                    self.handle_command(target, message, command);
+                }
             }
-            Err(e) => {
-                error!("❌ IRC connection check failed: {}", e);
-                false
+            Command::KICK(channel, nick, reason) if nick.eq_ignore_ascii_case(self.client.current_nickname()) => {
+                let by = message.source_nickname().unwrap_or_default();
+                warn!("👢 Kicked from {} by {}: {}", channel, by, reason.as_deref().unwrap_or(""));
+                self.schedule_rejoin(channel);
             }
+            Command::Response(Response::ERR_BANNEDFROMCHAN, args) => {
+                if let Some(channel) = args.get(1) {
+                    error!("❌ Banned from {}, not trying to rejoin", channel);
+                    self.rejoin_attempts.insert(channel.to_lowercase(), self.config.max_rejoin_attempts);
+                }
+            }
+            Command::Response(Response::RPL_YOUREOPER, _) => {
+                self.oper_pending = false;
+                info!("✅ Gained operator privileges");
+            }
+            // 464 also answers a wrong server password, but that ends registration before any OPER
+            Command::Response(response @ (Response::ERR_NOOPERHOST | Response::ERR_PASSWDMISMATCH), args) if self.oper_pending => {
+                self.oper_pending = false;
+                let reason = if *response == Response::ERR_NOOPERHOST { "no O-line for this host" } else { "wrong oper_password" };
+                warn!("⚠️ OPER failed: {} ({}), continuing without operator privileges", reason, args.last().map_or("", |text| text.as_str()));
+            }
+            // Joining +i channels needs an invite, e.g. from ChanServ or an operator
+            Command::INVITE(nick, channel) if nick.eq_ignore_ascii_case(self.client.current_nickname()) => {
+                let by = message.source_nickname().unwrap_or_default();
+                let configured = Self::all_channels(&self.config).iter().any(|c| c.eq_ignore_ascii_case(channel));
+                if configured {
+                    info!("✉️ Invited to {} by {}, joining", channel, by);
+                    if let Err(e) = self.join(channel) {
+                        error!("Failed to join {}: {}", channel, e);
+                    }
+                } else {
+                    debug!("Ignoring invite to {} by {}, not a configured channel", channel, by);
+                }
+            }
+            // Sent right before the server closes the link, e.g. on a K-line or ping timeout
+            Command::ERROR(reason) => {
+                warn!("⚠️ IRC server closed the link: {}", reason);
+                self.closing = Some(self.server_error_delay(reason));
+            }
+            _ => {}
         }
     }
 
-    fn load_seen_ids(announced_file: &str) -> Result<HashSet<SeenItem>, Box<dyn std::error::Error>> {
-        debug!("Load seen list from file ...");
-        if !Path::new(announced_file).exists() {
-            let file = OpenOptions::new()
-                .write(true)
-                .truncate(true)
-                .open(announced_file)?;
+    // The answer (381 or an error) is handled in handle_incoming, failing to become an
+    // operator doesn't stop the bot from announcing
+    fn send_oper(&mut self) {
+        let user = self.config.oper_user.as_deref().unwrap_or(&self.config.nickname);
+        let password = self.config.oper_password.as_ref().unwrap_or(&self.config.password);
+        info!("⏳ Attempting to gain operator privileges as {} ...", user);
+        match self.client.send_oper(user, password.expose()) {
+            Ok(()) => self.oper_pending = true,
+            Err(e) => warn!("⚠️ Failed to send OPER: {}", e),
+        }
+    }
 
-            let mut writer = BufWriter::new(file);
-            serde_json::to_writer(&mut writer, &Vec::<SeenItem>::new())?;
-            writer.flush()?;
+    // Kills and bans aren't lifted by reconnecting right away, so they get the longest
+    // delay. Other closes back off longer the more often they repeat within the window.
+    fn server_error_delay(&mut self, reason: &str) -> Duration {
+        let repeated = self.last_server_error.is_some_and(|at| at.elapsed() < SERVER_ERROR_WINDOW);
+        self.server_errors = if repeated { self.server_errors + 1 } else { 1 };
+        self.last_server_error = Some(Instant::now());
 
-            return Ok(HashSet::new());
+        let reason = reason.to_lowercase();
+        if BAN_PATTERNS.iter().any(|pattern| reason.contains(pattern)) {
+            return MAX_SERVER_ERROR_DELAY;
         }
+        SERVER_ERROR_DELAY.saturating_mul(2u32.saturating_pow(self.server_errors - 1)).min(MAX_SERVER_ERROR_DELAY)
+    }
+
+    pub fn has_closing(&self) -> bool {
+        self.closing.is_some()
+    }
+
+    // The reconnect delay asked for by a server ERROR, if one arrived
+    pub fn take_closing(&mut self) -> Option<Duration> {
+        self.closing.take()
+    }
+
+    // Rejoin a channel we were kicked from after a delay, up to max_rejoin_attempts times per connection
+    fn schedule_rejoin(&mut self, channel: &str) {
+        let attempts = self.rejoin_attempts.entry(channel.to_lowercase()).or_insert(0);
+        if *attempts >= self.config.max_rejoin_attempts {
+            error!("❌ Giving up on rejoining {} after {} attempts", channel, attempts);
+            return;
+        }
+        *attempts += 1;
+
+        let delay = Duration::from_secs(self.config.rejoin_delay_secs);
+        info!("⏳ Rejoining {} in {}s (attempt {}/{})", channel, delay.as_secs(), attempts, self.config.max_rejoin_attempts);
+        let sender = self.client.sender();
+        let key = Self::channel_key(&self.config, channel).map(|key| key.expose().to_string());
+        let channel = channel.to_string();
+        tokio::spawn(async move {
+            sleep(delay).await;
+            let result = match &key {
+                Some(key) => sender.send_join_with_keys(&channel, key),
+                None => sender.send_join(&channel),
+            };
+            if let Err(e) = result {
+                error!("Failed to rejoin {}: {}", channel, e);
+            }
+        });
+    }
 
-        let file_content = fs::read_to_string(announced_file)?;
-        let seen_items: Vec<SeenItem> = serde_json::from_str(&file_content)?;
+    pub fn announcing(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.announcing)
+    }
 
-        Ok(seen_items.into_iter().collect())
+    pub fn is_announcing(&self) -> bool {
+        self.announcing.load(Ordering::Relaxed)
     }
 
-    async fn save_seen_ids(&self) -> Result<(), Box<dyn std::error::Error>> {
-        debug!("Saving ID & timestamp to file ...");
+    pub fn set_control(&mut self, control: mpsc::Sender<ControlRequest>) {
+        self.control = Some(control);
+    }
 
-        // Acquire the lock only for the duration of cloning the seen IDs
-        let seen_items: Vec<SeenItem> = {
-            let seen = self.seen_ids.lock().await;
-            seen.iter().cloned().collect()
+    fn handle_command(&self, channel: &str, message: &Message, command: BotCommand) {
+        let nick = message.source_nickname().unwrap_or_default();
+        if command.is_admin() {
+            self.handle_admin_command(channel, nick, message, command);
+            return;
+        }
+        if !self.may_command(channel, nick, message) {
+            debug!("Ignoring command from {} in {}, not a channel operator or allowed mask", nick, channel);
+            return;
+        }
+
+        match command {
+            BotCommand::Last(count) => self.replay(channel, nick, count),
+            BotCommand::Latest => self.replay(channel, nick, 1),
+            BotCommand::Stats => {
+                let last_fetch = match self.last_fetch {
+                    Some(last_fetch) => format!("{}s ago", last_fetch.elapsed().as_secs()),
+                    None => "never".to_string(),
+                };
+                self.reply(channel, &format!(
+                    "Seen: {} | Uptime: {}s | Last fetch: {}",
+                    Metrics::get(&METRICS.seen_items),
+                    self.started.elapsed().as_secs(),
+                    last_fetch,
+                ));
+            }
+            BotCommand::Help => self.reply(channel, &help()),
+            BotCommand::Pause | BotCommand::Resume | BotCommand::Reload => {}
+        }
+    }
+
+    // Admin commands are only accepted from a prefix matching one of the admins masks
+    fn handle_admin_command(&self, channel: &str, nick: &str, message: &Message, command: BotCommand) {
+        let prefix = message.prefix.as_ref().map(|prefix| prefix.to_string()).unwrap_or_default();
+        let is_admin = matches!(message.prefix, Some(Prefix::Nickname(..)))
+            && self.config.admins.iter().any(|pattern| matches_hostmask(&prefix, pattern));
+        if !is_admin {
+            warn!("🚫 Denied {:?} from {} in {}, not an admin", command, prefix, channel);
+            if let Err(e) = self.client.send_notice(nick, "Permission denied") {
+                error!("Failed to send denial to {}: {}", nick, e);
+            }
+            return;
+        }
+
+        let command = match command {
+            BotCommand::Pause => ControlCommand::Pause,
+            BotCommand::Resume => ControlCommand::Resume,
+            BotCommand::Reload => ControlCommand::Reload,
+            _ => return,
+        };
+        let Some(control) = &self.control else {
+            return;
         };
 
-        debug!("Writing to file ...");
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&self.announced_file)?;
-        let mut writer = BufWriter::new(file);
-        serde_json::to_writer(&mut writer, &seen_items)?;
-        writer.flush()?;
+        info!("🛠️ {} requested {:?} in {}", nick, command, channel);
+        let (reply, response) = oneshot::channel();
+        if control.try_send(ControlRequest { command, reply }).is_err() {
+            self.reply(channel, "error: busy, try again");
+            return;
+        }
 
-        Ok(())
+        // The main loop answers once it gets to the request, relay that to the channel
+        let sender = self.client.sender();
+        let channel = channel.to_string();
+        tokio::spawn(async move {
+            if let Ok(response) = response.await {
+                if let Err(e) = sender.send_privmsg(&channel, response) {
+                    error!("Failed to reply in {}: {}", channel, e);
+                }
+            }
+        });
     }
 
-    pub async fn should_announce(&self, item: &ApiItem) -> bool {
-        let seen_item = SeenItem {
-            id: item.id.clone(),
-            bumped_at: item.attributes.bumped_at.clone(),
+    // Channel operators and anyone matching command_allow may run commands
+    fn may_command(&self, channel: &str, nick: &str, message: &Message) -> bool {
+        let hostmask = match &message.prefix {
+            Some(Prefix::Nickname(nick, user, host)) => format!("{}!{}@{}", nick, user, host),
+            _ => nick.to_string(),
         };
+        if self.config.command_allow.iter().any(|mask| matches_mask(mask, nick, &hostmask)) {
+            return true;
+        }
 
-        let mut seen = self.seen_ids.lock().await;
+        self.client.list_users(channel)
+            .and_then(|users| users.into_iter().find(|u| u.get_nickname().eq_ignore_ascii_case(nick)))
+            .is_some_and(|user| user.highest_access_level() >= AccessLevel::HalfOp)
+    }
 
-        // First check if we have an exact match (same ID and timestamp)
-        if seen.contains(&seen_item) {
-            debug!("⏭️ Already announced ID {}, skipping", seen_item.id);
-            return false;
+    fn replay(&self, channel: &str, nick: &str, count: usize) {
+        info!("🔁 {} requested the last {} announcements in {}", nick, count, channel);
+        let skip = self.recent.len().saturating_sub(count);
+        for message in self.recent.iter().skip(skip) {
+            if let Err(e) = self.client.send_privmsg(channel, self.sanitize_for(channel, message)) {
+                error!("Failed to replay announcement: {}", e);
+                return;
+            }
         }
+    }
 
-        // If we get here, either:
-        // 1. The item doesn't exist in the set, or
-        // 2. It exists but with a different timestamp
-        // So we remove any existing entry with the same ID (if present)
-        seen.retain(|s| s.id != seen_item.id);
-        true
+    fn reply(&self, channel: &str, text: &str) {
+        if let Err(e) = self.client.send_privmsg(channel, text) {
+            error!("Failed to reply in {}: {}", channel, e);
+        }
     }
 
-    pub async fn send_message(&mut self, item: ApiItem) -> Result<(), Box<dyn Error + Send + Sync>> {
-        // Format and announce the message
-        let message = self.format_message(&item).await;
+    // Strip color/formatting codes when the target channel is +c
+    fn sanitize_for(&self, target: &str, message: &str) -> String {
+        let no_colors = self.channel_modes.get(&target.to_lowercase())
+            .is_some_and(|modes| modes.contains(&'c'));
 
-        // Try to send the message
-        info!("📢 Announcing: {}", message);
-        // Try to send message
-        self.client.send_privmsg(&self.config.channel, &message)?;
+        if no_colors {
+            strip_control_codes(message)
+        } else {
+            message.to_string()
+        }
+    }
 
-        // Verify connected
-        if !self.verify_connected().await {
-            warn!("❌ Failed to announce ID {}, not connected to channel {}", &item.id, &self.config.channel);
-            warn!("Will not store this ID to the log file");
-            return Err("Message failed to send to channel".into());
+    // JOIN with the channel's key if one is configured
+    fn join(&self, channel: &str) -> irc::error::Result<()> {
+        match Self::channel_key(&self.config, channel) {
+            Some(key) => self.client.send_join_with_keys(channel, key.expose()),
+            None => self.client.send_join(channel),
         }
+    }
 
-        debug!("✅ Message confirmed, marking item with ID {} as seen", &item.id);
-        self.mark_as_announced(&item).await;
-        Ok(())
+    fn channel_key<'a>(config: &'a IrcConfig, channel: &str) -> Option<&'a Secret> {
+        if channel.eq_ignore_ascii_case(&config.channel) {
+            if let Some(key) = &config.channel_key {
+                return Some(key);
+            }
+        }
+        config.channel_keys.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(channel))
+            .map(|(_, key)| key)
     }
 
-    pub async fn mark_as_announced(&self, item: &ApiItem) {
-        let seen_item = SeenItem {
-            id: item.id.clone(),
-            bumped_at: item.attributes.bumped_at.clone(),
+    // We registered with an alternate nick, take the primary one back if configured to
+    fn regain_nick(&self) -> irc::error::Result<()> {
+        let Some(command) = &self.config.regain_command else {
+            warn!("⚠️ Connected with an alternate nick, {} is in use", self.config.nickname);
+            return Ok(());
         };
 
-        // Only hold lock for the insert operation
-        {
-            let mut seen = self.seen_ids.lock().await;
-            seen.insert(seen_item);
+        info!("🔁 Regaining nick {} via NickServ {} ...", self.config.nickname, command);
+        self.client.send_privmsg(NICKSERV, format!("{} {} {}", command, self.config.nickname, self.config.ns_password.expose()))?;
+        self.client.send(Command::NICK(self.config.nickname.to_string()))
+    }
+
+    // Register while negotiating SASL PLAIN, ends capability negotiation once authenticated
+    async fn register_with_sasl(&mut self) -> irc::error::Result<()> {
+        info!("🪪  Authenticating via SASL ...");
+        self.client.send_cap_req(&[Capability::Sasl])?;
+        let server_password = self.config.server_password();
+        if !server_password.is_empty() {
+            self.client.send(Command::PASS(server_password))?;
+        }
+        self.client.send(Command::NICK(self.config.nickname.to_string()))?;
+        self.client.send(Command::USER(
+            self.config.username.as_deref().unwrap_or(&self.config.nickname).to_string(),
+            "0".to_string(),
+            self.config.realname.as_deref().unwrap_or(&self.config.nickname).to_string(),
+        ))?;
+
+        loop {
+            let message = self.next_message().await?;
+
+            match &message.command {
+                Command::CAP(_, CapSubCommand::ACK, _, _) => {
+                    self.client.send_sasl_plain()?;
+                }
+                Command::CAP(_, CapSubCommand::NAK, _, _) => {
+                    return Err(Self::sasl_error("server does not support SASL"));
+                }
+                Command::AUTHENTICATE(data) if data == "+" => {
+                    let account = self.config.sasl_username.as_deref().unwrap_or(&self.config.nickname);
+                    let credentials = format!("{}\0{}\0{}", account, account, self.config.ns_password.expose());
+                    let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+
+                    // Payloads are sent in 400 byte chunks, an exact multiple ends with an empty one
+                    for chunk in encoded.as_bytes().chunks(400) {
+                        self.client.send_sasl(String::from_utf8_lossy(chunk))?;
+                    }
+                    if encoded.len() % 400 == 0 {
+                        self.client.send_sasl("+")?;
+                    }
+                }
+                Command::Response(Response::RPL_SASLSUCCESS, _) => {
+                    info!("✅ SASL authentication successful");
+                    self.client.send(Command::CAP(None, CapSubCommand::END, None, None))?;
+                    return Ok(());
+                }
+                Command::Response(
+                    Response::ERR_SASLFAIL | Response::ERR_SASLTOOLONG | Response::ERR_SASLABORT | Response::ERR_NICKLOCKED,
+                    args,
+                ) => {
+                    let reason = args.last().map_or("authentication failed", |reason| reason.as_str());
+                    return Err(Self::sasl_error(reason));
+                }
+                _ => {}
+            }
         }
-        
-        if let Err(e) = self.save_seen_ids().await {
-            error!("Failed to save seen IDs: {}", e);
+    }
+
+    fn sasl_error(reason: &str) -> irc::error::Error {
+        error!("❌ SASL authentication failed: {}", reason);
+        std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("SASL authentication failed: {}", reason),
+        ).into()
+    }
+
+    // Wait for either the NickServ NOTICE or a WHOIS reply showing we're logged in (330 RPL_WHOISACCOUNT)
+    async fn wait_for_identification(&mut self, grace: Duration) -> irc::error::Result<()> {
+        let deadline = Instant::now() + grace;
+        let mut whois_check = interval(Duration::from_secs(5));
+        // The first tick fires immediately, give NickServ a moment before asking
+        whois_check.tick().await;
+
+        loop {
+            tokio::select! {
+                _ = sleep_until(deadline) => {
+                    error!("❌ NickServ identification not confirmed within {}s", grace.as_secs());
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "NickServ identification not confirmed",
+                    ).into());
+                }
+
+                _ = whois_check.tick() => {
+                    debug!("Checking account status via WHOIS ...");
+                    self.client.send(Command::WHOIS(None, self.config.nickname.to_string()))?;
+                }
+
+                message = self.next_message() => {
+                    let message = message?;
+                    // Anyone can send a NOTICE, only NickServ's say how identification went
+                    let from_nickserv = message.source_nickname().is_some_and(|nick| nick.eq_ignore_ascii_case(NICKSERV));
+
+                    match &message.command {
+                        Command::NOTICE(_target, content) if from_nickserv && content.contains(&self.config.ns_success_pattern) => {
+                            info!("✅ NickServ identification successful");
+                            return Ok(());
+                        }
+                        Command::NOTICE(_target, content) if from_nickserv && NS_FAILURE_PATTERNS.iter().any(|pattern| content.contains(pattern)) => {
+                            error!("❌ NickServ identification failed: {}", content);
+                            return Err(std::io::Error::new(
+                                std::io::ErrorKind::PermissionDenied,
+                                format!("NickServ identification failed: {}", content),
+                            ).into());
+                        }
+                        Command::Raw(code, args) if code == "330"
+                            && args.get(1).is_some_and(|nick| nick.eq_ignore_ascii_case(&self.config.nickname)) =>
+                        {
+                            info!("✅ NickServ identification confirmed via WHOIS");
+                            return Ok(());
+                        }
+                        _ => {}
+                    }
+                }
+            }
         }
     }
 
-    async fn format_message(&self, item: &crate::web_api::ApiItem) -> String {
-        // Handle optional resolution
-        let resolution = item.attributes.resolution.as_deref().unwrap_or("N/A");
+    fn all_channels(config: &IrcConfig) -> Vec<String> {
+        let mut channels = vec![config.channel.to_string()];
+        for channel in &config.channels {
+            if !channels.iter().any(|c| c.eq_ignore_ascii_case(channel)) {
+                channels.push(channel.to_string());
+            }
+        }
+        channels
+    }
 
-        // Determine internal status
-        let internal_status = match item.attributes.internal {
-            0 => "No",
-            1 => "Yes",
-            _ => "N/A",
+    // Pick the channels an item goes to: the channel named by the optional per-item
+    // routing hint (the default channel if it names none configured), or every configured
+    // channel. In both cases limited to the channels whose filter accepts the item
+    pub fn route_targets(&self, item: &Release) -> Vec<String> {
+        Self::targets_for(&self.config, item)
+    }
+
+    fn targets_for(config: &IrcConfig, item: &Release) -> Vec<String> {
+        let hint = match config.route_field.as_deref()
+            .and_then(|field| item.attributes.extra_str(field))
+        {
+            Some(hint) => hint.trim_start_matches(['#', '&']),
+            None => {
+                return Self::all_channels(config).into_iter()
+                    .filter(|channel| Self::channel_accepts(config, channel, item))
+                    .collect();
+            }
         };
 
-        // Determine double upload status
-        let du_status = if item.attributes.double_upload {
-            "Yes"
-        } else {
-            "No"
+        // The hinted channel's filter still applies, a rejected item goes nowhere
+        let channel = match Self::all_channels(config).into_iter()
+            .find(|c| c.trim_start_matches(['#', '&']).eq_ignore_ascii_case(hint))
+        {
+            Some(channel) => channel,
+            None => {
+                warn!("Routing hint '{}' for ID {} matches no configured channel, announcing to {}", hint, item.id, config.channel);
+                config.channel.to_string()
+            }
         };
+        if !Self::channel_accepts(config, &channel, item) {
+            debug!("ID {} is routed to {}, whose filter rejects it", item.id, channel);
+            return vec![];
+        }
+        vec![channel]
+    }
+
+    fn channel_accepts(config: &IrcConfig, channel: &str, item: &Release) -> bool {
+        match config.filters.iter().find(|(name, _)| name.eq_ignore_ascii_case(channel)) {
+            Some((_, filter)) => matches_filter(item, filter),
+            None => true,
+        }
+    }
+
+    // Send a PING with a unique token and wait for the matching PONG
+    pub async fn verify_connected(&mut self) -> bool {
+        debug!("Performing IRC connection check (Ping) ...");
+
+        self.ping_seq += 1;
+        let token = format!("announcarr-{}", self.ping_seq);
+        if let Err(e) = self.client.send(Command::PING(token.to_string(), None)) {
+            error!("❌ IRC connection check failed: {}", e);
+            return false;
+        }
+
+        let deadline = sleep(PING_TIMEOUT);
+        tokio::pin!(deadline);
+
+        loop {
+            tokio::select! {
+                _ = &mut deadline => {
+                    error!("❌ IRC connection check failed: no PONG within {}s", PING_TIMEOUT.as_secs());
+                    return false;
+                }
+
+                message = self.stream.next() => {
+                    let message = match message {
+                        Some(Ok(message)) => message,
+                        Some(Err(e)) => {
+                            error!("❌ IRC connection check failed: {}", e);
+                            return false;
+                        }
+                        None => {
+                            error!("❌ IRC connection check failed: connection closed");
+                            return false;
+                        }
+                    };
+
+                    // Servers answer with either "PONG <server> :<token>" or "PONG <token>"
+                    if let Command::PONG(first, second) = &message.command {
+                        if *first == token || second.as_deref() == Some(token.as_str()) {
+                            debug!("✅ IRC connection ok");
+                            self.last_verified = Some(Instant::now());
+                            return true;
+                        }
+                    }
+
+                    // Don't drop anything else that arrives while waiting
+                    self.handle_incoming(&message);
+                }
+            }
+        }
+    }
+
+    pub fn note_fetch(&mut self) {
+        self.last_fetch = Some(Instant::now());
+    }
+
+    pub async fn send_message(&mut self, item: &Release, targets: Vec<String>) -> Result<(), NotifyError> {
+        // Failed announcements are kept for a retry after the next reconnect, to the channels that missed them
+        if let Err((e, failed)) = self.try_send(item, &targets).await {
+            self.queue_retry(item.clone(), failed);
+            return Err(e);
+        }
+        self.retry_queue.retain(|(queued, _)| queued.id != item.id);
+        Ok(())
+    }
+
+    // On failure returns the targets that may not have received the announcement
+    async fn try_send(&mut self, item: &Release, targets: &[String]) -> Result<(), (NotifyError, Vec<String>)> {
+        // Format and announce the message, the prefix and suffix count towards the line limit
+        let message = wrap_announcement(&format_item(item, &self.announce, self.announce.colors), &self.announce);
+
+        if self.dry_run {
+            info!("📝 [dry run] Would announce to {}: {}", targets.join(", "), message);
+            return Ok(());
+        }
+
+        // Try to send the message to every target channel
+        let mut failed = Vec::new();
+        let mut error = None;
+        for target in targets {
+            self.throttle().await;
+            info!("📢 Announcing to {}: {}", target, message);
+            if let Err(e) = self.deliver(target, &message) {
+                failed.push(target.clone());
+                error.get_or_insert(NotifyError::from(e));
+            }
+        }
+        if let Some(e) = error {
+            warn!("❌ Failed to announce ID {} to {}", &item.id, failed.join(", "));
+            return Err((e, failed));
+        }
+
+        // Whatever was sent since the connection was last known to be alive may be lost
+        if !self.ensure_connected().await {
+            warn!("❌ Failed to announce ID {}, not connected to {}", &item.id, targets.join(", "));
+            return Err((NotifyError::NotConnected, targets.to_vec()));
+        }
+
+        // Remember the announcement for !last
+        self.recent.push_back(message);
+        while self.recent.len() > self.config.replay_buffer {
+            self.recent.pop_front();
+        }
+
+        Metrics::inc(&METRICS.announcements_sent);
+        debug!("✅ Message confirmed for ID {}", &item.id);
+        Ok(())
+    }
+
+    // Announce several items in as few lines as fit, failed ones are retried one by one
+    pub async fn send_batch(&mut self, items: &[Release], targets: Vec<String>) -> Result<(), NotifyError> {
+        if let Err((e, failed)) = self.try_send_batch(items, &targets).await {
+            for item in items {
+                self.queue_retry(item.clone(), failed.clone());
+            }
+            return Err(e);
+        }
+        self.retry_queue.retain(|(queued, _)| !items.iter().any(|item| item.id == queued.id));
+        Ok(())
+    }
+
+    async fn try_send_batch(&mut self, items: &[Release], targets: &[String]) -> Result<(), (NotifyError, Vec<String>)> {
+        let mut lines = Vec::new();
+        let mut failed = Vec::new();
+        let mut error = None;
+
+        // The line limit depends on the target, so each gets its own split
+        for target in targets {
+            let limit = self.payload_limit(target).saturating_sub(wrapping_len(&self.announce));
+            let target_lines: Vec<String> = format_batch(items, &self.announce, self.announce.colors, limit).iter()
+                .map(|line| wrap_announcement(line, &self.announce))
+                .collect();
+            for line in &target_lines {
+                if self.dry_run {
+                    info!("📝 [dry run] Would announce to {}: {}", target, line);
+                    continue;
+                }
+                self.throttle().await;
+                info!("📢 Announcing to {}: {}", target, line);
+                if let Err(e) = self.deliver(target, line) {
+                    failed.push(target.clone());
+                    error.get_or_insert(NotifyError::from(e));
+                    break;
+                }
+            }
+            if !failed.contains(target) {
+                lines.extend(target_lines);
+            }
+        }
+        if self.dry_run {
+            return Ok(());
+        }
+        if let Some(e) = error {
+            warn!("❌ Failed to announce a batch of {} items to {}", items.len(), failed.join(", "));
+            return Err((e, failed));
+        }
+
+        if !self.ensure_connected().await {
+            warn!("❌ Failed to announce a batch of {} items, not connected to {}", items.len(), targets.join(", "));
+            return Err((NotifyError::NotConnected, targets.to_vec()));
+        }
+
+        self.recent.extend(lines);
+        while self.recent.len() > self.config.replay_buffer {
+            self.recent.pop_front();
+        }
+
+        for item in items {
+            Metrics::inc(&METRICS.announcements_sent);
+            debug!("✅ Message confirmed for ID {}", &item.id);
+        }
+        Ok(())
+    }
+
+    // Check the connection with a PING round trip, unless one succeeded within LIVENESS_WINDOW,
+    // so a burst of announcements doesn't wait for a PONG after every single one
+    async fn ensure_connected(&mut self) -> bool {
+        if self.last_verified.is_some_and(|verified| verified.elapsed() < LIVENESS_WINDOW) {
+            return true;
+        }
+        self.verify_connected().await
+    }
+
+    fn queue_retry(&mut self, item: Release, targets: Vec<String>) {
+        // A refetched item replaces its earlier queue entry
+        self.retry_queue.retain(|(queued, _)| queued.id != item.id);
+        if self.retry_queue.len() >= self.config.retry_queue_size {
+            if let Some((dropped, _)) = self.retry_queue.pop_front() {
+                warn!("⚠️ Retry queue full, dropping announcement of ID {}", dropped.id);
+            }
+        }
+        if self.config.retry_queue_size > 0 {
+            debug!("Queued ID {} for retry after reconnect", item.id);
+            self.retry_queue.push_back((item, targets));
+        }
+    }
+
+    // Whether an item has to wait for the announcements-per-minute cap, takes a slot if not.
+    // Nothing jumps ahead of items already held.
+    pub fn must_hold(&mut self) -> bool {
+        !self.held.is_empty() || self.announce_rate.as_mut().is_some_and(|rate| !rate.try_take())
+    }
+
+    pub fn is_held(&self, item: &Release) -> bool {
+        self.held.iter().any(|held| held.id == item.id)
+    }
+
+    pub fn held_len(&self) -> usize {
+        self.held.len()
+    }
+
+    // Items dropped from a full queue stay unmarked, so a later fetch brings them back
+    pub fn hold(&mut self, item: Release) {
+        if self.held.len() >= self.config.held_queue_size {
+            if let Some(dropped) = self.held.pop_front() {
+                warn!("⚠️ Held queue full, leaving ID {} for a later fetch", dropped.id);
+            }
+        }
+        if self.config.held_queue_size > 0 {
+            debug!("Holding ID {} until the announcement cap allows it", item.id);
+            self.held.push_back(item);
+        }
+    }
+
+    // As many held items as the cap allows now, oldest first. Kept held while paused.
+    pub fn take_held(&mut self) -> Vec<Release> {
+        let mut released = Vec::new();
+        if !self.is_announcing() {
+            return released;
+        }
+        while !self.held.is_empty() && self.announce_rate.as_mut().is_none_or(TokenBucket::try_take) {
+            released.extend(self.held.pop_front());
+        }
+        released
+    }
+
+    // Everything that failed before the reconnect, in the original order. Kept queued while paused.
+    pub fn take_retries(&mut self) -> Vec<(Release, Vec<String>)> {
+        if !self.is_announcing() {
+            return Vec::new();
+        }
+        self.retry_queue.drain(..).collect()
+    }
+
+    // Send QUIT and give the connection a moment to flush it
+    pub async fn quit(&mut self) {
+        if let Err(e) = self.client.send_quit("") {
+            warn!("Failed to send QUIT: {}", e);
+            return;
+        }
+
+        let _ = tokio::time::timeout(PING_TIMEOUT, async {
+            while let Some(Ok(_)) = self.stream.next().await {}
+        }).await;
+    }
+
+    // Keep at least send_delay_ms between announcements so bursts don't trip flood limits
+    async fn throttle(&mut self) {
+        let delay = Duration::from_millis(self.config.send_delay_ms);
+        if let Some(last_send) = self.last_send {
+            sleep_until(last_send + delay).await;
+        }
+        self.last_send = Some(Instant::now());
+    }
+
+    // Announcements go out as NOTICE where channel rules require it. Messages too long
+    // for a single line are split so the server doesn't truncate them.
+    fn deliver(&self, target: &str, text: &str) -> irc::error::Result<()> {
+        let text = self.sanitize_for(target, text);
+        for line in split_message(&text, self.payload_limit(target)) {
+            if self.config.use_notice {
+                self.client.send_notice(target, line)?;
+            } else {
+                self.client.send_privmsg(target, line)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Bytes left for the text after "PRIVMSG <target> :" and the CRLF, control codes count too
+    fn payload_limit(&self, target: &str) -> usize {
+        let command = if self.config.use_notice { "NOTICE" } else { "PRIVMSG" };
+        let overhead = command.len() + 1 + target.len() + 2 + 2;
+        MAX_LINE_LEN.saturating_sub(overhead)
+    }
+
+    pub fn send_line(&self, line: &str) -> irc::error::Result<()> {
+        if self.dry_run {
+            info!("📝 [dry run] Would send: {}", line);
+            return Ok(());
+        }
+
+        for channel in Self::all_channels(&self.config) {
+            self.deliver(&channel, line)?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for IrcClient {
+    fn name(&self) -> &str {
+        "IRC"
+    }
+
+    async fn notify(&mut self, item: &Release) -> Result<(), NotifyError> {
+        let targets = self.route_targets(item);
+        self.send_message(item, targets).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    // A single-connection IRC server that sends `greeting` right away and answers each line
+    // it receives with whatever `respond` returns, recording the lines it received
+    async fn fake_server<F>(greeting: &[&str], mut respond: F) -> (u16, Arc<Mutex<Vec<String>>>)
+    where
+        F: FnMut(&str) -> Vec<String> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let greeting: Vec<String> = greeting.iter().map(|line| line.to_string()).collect();
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let lines = Arc::clone(&received);
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = socket.into_split();
+            for line in greeting {
+                writer.write_all(format!("{}\r\n", line).as_bytes()).await.unwrap();
+            }
+
+            let mut reader = BufReader::new(reader).lines();
+            while let Ok(Some(line)) = reader.next_line().await {
+                let replies = respond(&line);
+                lines.lock().unwrap().push(line);
+                for reply in replies {
+                    if writer.write_all(format!("{}\r\n", reply).as_bytes()).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        (port, received)
+    }
+
+    fn irc_config(port: u16, extra: serde_json::Value) -> IrcConfig {
+        let mut config = serde_json::json!({
+            "server": "127.0.0.1",
+            "port": port,
+            "use_tls": false,
+            "channel": "#announce",
+            "nickname": "announcer",
+            "ns_password": "hunter2",
+        });
+        config.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(config).unwrap()
+    }
+
+    async fn client(port: u16, extra: serde_json::Value) -> IrcClient {
+        let app: AppConfig = serde_json::from_value(serde_json::json!({ "announced_file": "announced.json" })).unwrap();
+        IrcClient::new(irc_config(port, extra), AnnounceConfig::default(), app).await.unwrap()
+    }
+
+    fn received(lines: &Mutex<Vec<String>>, prefix: &str) -> bool {
+        lines.lock().unwrap().iter().any(|line| line.starts_with(prefix))
+    }
+
+    fn routed(section: Option<&str>, category: &str) -> Release {
+        let mut attributes = serde_json::json!({
+            "name": "Some.Release",
+            "category": category,
+            "download_link": "https://tracker.example/torrent/download/1.passkey",
+            "bumped_at": "2024-01-01T12:00:00Z",
+        });
+        if let Some(section) = section {
+            attributes["section"] = section.into();
+        }
+        serde_json::from_value(serde_json::json!({ "id": "1", "attributes": attributes })).unwrap()
+    }
+
+    fn routing_config() -> IrcConfig {
+        irc_config(6667, serde_json::json!({
+            "channels": ["#tv", "#movies"],
+            "route_field": "section",
+            "filters": { "#movies": { "categories": ["Movies"] } },
+        }))
+    }
+
+    #[test]
+    fn hint_routes_to_the_named_channel() {
+        let config = routing_config();
+        assert_eq!(IrcClient::targets_for(&config, &routed(Some("tv"), "TV")), ["#tv"]);
+        assert_eq!(IrcClient::targets_for(&config, &routed(Some("#Movies"), "Movies")), ["#movies"]);
+        assert_eq!(IrcClient::targets_for(&config, &routed(None, "TV")), ["#announce", "#tv"]);
+    }
+
+    #[test]
+    fn hinted_channel_rejecting_the_item_announces_nowhere() {
+        assert!(IrcClient::targets_for(&routing_config(), &routed(Some("movies"), "TV")).is_empty());
+    }
+
+    #[test]
+    fn unknown_hint_falls_back_to_the_default_channel() {
+        assert_eq!(IrcClient::targets_for(&routing_config(), &routed(Some("music"), "Music")), ["#announce"]);
+    }
+
+    // Registers, confirms IDENTIFY if `identified` and lets the client join
+    fn network(identified: bool) -> impl FnMut(&str) -> Vec<String> + Send + 'static {
+        move |line| {
+            let replies: &[&str] = match line {
+                line if line.starts_with("USER ") => &[":irc.example 001 announcer :Welcome to the network, announcer"],
+                "PRIVMSG NickServ :IDENTIFY announcer hunter2" if identified => &[
+                    ":NickServ!NickServ@services. NOTICE announcer :Password accepted - you are now recognized.",
+                ],
+                "JOIN #announce" => &[
+                    ":announcer!announcer@127.0.0.1 JOIN #announce",
+                    ":irc.example 353 announcer = #announce :announcer @op",
+                    ":irc.example 366 announcer #announce :End of /NAMES list.",
+                ],
+                _ => &[],
+            };
+            replies.iter().map(|reply| reply.to_string()).collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn connect_registers_identifies_and_joins() {
+        let (port, lines) = fake_server(&[], network(true)).await;
+        let mut client = client(port, serde_json::json!({})).await;
+
+        client.connect().await.unwrap();
+        assert!(received(&lines, "NICK announcer"));
+        assert!(received(&lines, "PRIVMSG NickServ :IDENTIFY announcer hunter2"));
+        assert!(received(&lines, "JOIN #announce"));
+    }
+
+    #[tokio::test]
+    async fn connect_fails_when_identification_times_out() {
+        let (port, lines) = fake_server(&[], network(false)).await;
+        let mut client = client(port, serde_json::json!({ "ns_grace_secs": 1 })).await;
+
+        let e = client.connect().await.unwrap_err();
+        assert!(matches!(&e, irc::error::Error::Io(e) if e.kind() == std::io::ErrorKind::TimedOut), "{:?}", e);
+        // Channels are only joined once identified
+        assert!(!received(&lines, "JOIN"));
+    }
+
+    #[tokio::test]
+    async fn identification_is_confirmed_by_nickserv_notice() {
+        let (port, _) = fake_server(&[
+            ":NickServ!NickServ@services. NOTICE announcer :This nickname is registered.",
+            ":NickServ!NickServ@services. NOTICE announcer :Password accepted - you are now recognized.",
+        ], |_| vec![]).await;
+        let mut client = client(port, serde_json::json!({})).await;
+
+        client.wait_for_identification(Duration::from_secs(10)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejected_password_fails_identification() {
+        let (port, _) = fake_server(&[
+            ":NickServ!NickServ@services. NOTICE announcer :Invalid password for announcer.",
+        ], |_| vec![]).await;
+        let mut client = client(port, serde_json::json!({})).await;
+
+        let e = client.wait_for_identification(Duration::from_secs(10)).await.unwrap_err();
+        assert!(matches!(&e, irc::error::Error::Io(e) if e.kind() == std::io::ErrorKind::PermissionDenied), "{:?}", e);
+    }
+
+    #[tokio::test]
+    async fn notices_from_others_are_ignored() {
+        let (port, _) = fake_server(&[
+            ":mallory!mallory@example.org NOTICE announcer :Password accepted - you are now recognized.",
+            ":NickServ!NickServ@services. NOTICE announcer :Invalid password for announcer.",
+        ], |_| vec![]).await;
+        let mut spoofed = client(port, serde_json::json!({})).await;
+        assert!(spoofed.wait_for_identification(Duration::from_secs(10)).await.is_err());
+
+        let (port, _) = fake_server(&[
+            ":mallory!mallory@example.org NOTICE announcer :Password incorrect.",
+            ":nickserv!NickServ@services. NOTICE announcer :Password accepted - you are now recognized.",
+        ], |_| vec![]).await;
+        let mut client = client(port, serde_json::json!({})).await;
+        client.wait_for_identification(Duration::from_secs(10)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn identification_is_confirmed_by_whois_account() {
+        // Some services never send a confirmation NOTICE, 330 RPL_WHOISACCOUNT still shows the login
+        let (port, lines) = fake_server(&[], |line| match line {
+            "WHOIS announcer" => vec![
+                ":irc.example 330 announcer someone else :is logged in as".to_string(),
+                ":irc.example 330 announcer announcer announcer :is logged in as".to_string(),
+            ],
+            _ => vec![],
+        }).await;
+        let mut client = client(port, serde_json::json!({})).await;
 
-        // Convert Bytes to GB
-        let size_in_gb = ((item.attributes.size as f64 / (1024.0 * 1024.0 * 1024.0)) * 100.0).round() / 100.0;
-
-        // Extract the download link
-        let download_link = item.attributes.download_link
-            .replace("torrent", "torrents");
-        let download_link = download_link.rsplit_once('.').map(|x| x.0)
-            .unwrap_or("N/A");
-
-        // Format the message
-        format!(
-            "Category [{}] Type [{}] Name [{}] Resolution [{}] Freeleech [{}] Internal [{}] Double Upload [{}] Size [{} GiB] Uploader [{}] Url [{}]",
-            item.attributes.category,
-            item.attributes.r#type,
-            item.attributes.name,
-            resolution,
-            item.attributes.freeleech,
-            internal_status,
-            du_status,
-            size_in_gb,
-            item.attributes.uploader,
-            download_link,
-        )
+        client.wait_for_identification(Duration::from_secs(10)).await.unwrap();
+        assert!(received(&lines, "WHOIS announcer"));
     }
 }
@@ -1,78 +1,713 @@
-use std::error::Error;
-use tracing::{debug, info};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use tracing::{debug, info, warn, error};
 use tokio_stream::StreamExt as _;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
 use tokio::time::{interval, Instant, Duration};
+use tracing_subscriber::EnvFilter;
 
-use web_api::ApiHandler;
+use chrono::{DateTime, Utc};
+use poller::{open_pollers, CallLog, SourcePoller};
+use source::Release;
+use web_api::{parse_bumped_at, sort_oldest_first};
 use irc_client::IrcClient;
-use config::{load_config};
+use budget::{Admission, AnnounceBudget, CategoryLimiter};
+use config::{load_config, FilterConfig, LogFormat, NotifyPolicy};
+use control::{ControlCommand, ControlRequest};
+use discord::DiscordNotifier;
+use error::Error;
+use filter::{dedup_by_name, passes_filters};
+use health::HealthState;
+use notifier::Notifier;
+use pending::PendingReleases;
+use quiet_hours::QuietHours;
+use seen::{SeenState, SeenTracker};
+use store::open_store;
+use telegram::TelegramNotifier;
 
+mod budget;
+mod commands;
 mod config;
+mod control;
+mod discord;
+mod error;
+mod feed;
+mod filter;
+mod formatting;
+mod health;
+mod http;
 mod irc_client;
+mod metrics;
+mod notifier;
+mod pending;
+mod poller;
+mod quiet_hours;
+mod seen;
+mod source;
+mod store;
+mod telegram;
 mod web_api;
 
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(300);
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+// Fatal errors are logged readably instead of as the Debug output main would print
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() {
+    if let Err(e) = run().await {
+        error!("❌ {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run() -> Result<(), Error> {
+    // Load the configuration, logging is set up from it so errors are reported afterwards
+    let loaded = config_path().and_then(|path| {
+        load_config(&path)
+            .map(|config| (path, config))
+            .map_err(|e| e.to_string())
+    });
+
     // Logging
-    tracing_subscriber::fmt::init();
-    //tracing_subscriber::fmt().with_max_level(tracing::Level::DEBUG).init();
+    match &loaded {
+        Ok((_, config)) => init_logging(config.app.log_format, config.app.log_level.as_deref()),
+        Err(_) => init_logging(LogFormat::Text, None),
+    }
 
-    // Load the IRC configuration from the TOML file
-    debug!("Loading configuration file ...");
-    let config = load_config();
+    let (config_path, config) = loaded.unwrap_or_else(|e| {
+        error!("❌ {}", e);
+        std::process::exit(1);
+    });
+    debug!("Loaded configuration file {}", config_path.display());
+    let mut filter = config.filter.clone();
+
+    // Commands from the optional local control socket and from IRC admins
+    let (control_tx, mut control_rx) = mpsc::channel(16);
+    if let Some(path) = &config.app.control_socket {
+        control::spawn(path, control_tx.clone())?;
+    }
+
+    // Optional Prometheus metrics endpoint
+    if let Some(addr) = &config.app.metrics_addr {
+        metrics::spawn(addr).await?;
+    }
+
+    // Optional health check endpoint, unhealthy once no fetch succeeded for three API intervals
+    let slowest_interval = config.api.iter()
+        .map(|api| api.min_api_interval_secs.unwrap_or(config.app.min_api_interval_secs))
+        .max()
+        .unwrap_or(config.app.min_api_interval_secs);
+    let health = Arc::new(HealthState::new(Duration::from_secs(slowest_interval * 3)));
+    if let Some(addr) = &config.app.health_addr {
+        health::spawn(addr, Arc::clone(&health)).await?;
+    }
+
+    // Outputs besides IRC, they get the same items
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+    if let Some(discord) = config.discord.clone() {
+        notifiers.push(Box::new(DiscordNotifier::new(discord, config.announce.clone(), config.app.dry_run).map_err(Error::Notifier)?));
+    }
+    if let Some(telegram) = config.telegram.clone() {
+        notifiers.push(Box::new(TelegramNotifier::new(telegram, config.announce.clone(), config.app.dry_run).map_err(Error::Notifier)?));
+    }
+
+    let store = open_store(&config.app)?;
+    let mut announcer = Announcer {
+        seen: SeenTracker::new(store, &config.app).await,
+        notifiers,
+        policy: config.app.notify_policy,
+        skip_backlog: config.app.skip_backlog_on_first_run,
+        started_at: Utc::now(),
+        dedup_by_name: config.app.dedup_by_name,
+        batch_size: config.announce.batch_announcements,
+        pending: config.app.announce_delay_secs.map(PendingReleases::new),
+        category_limit: config.app.category_rate_limit.as_ref().map(CategoryLimiter::new),
+        budget: config.app.budget.as_ref().map(AnnounceBudget::new),
+    };
 
     // Initialize the IRC client
     debug!("Initialize IRC client ...");
     let mut irc_client = IrcClient::new(
         config.irc,
-        config.app.announced_file,
+        config.announce,
+        config.app.clone(),
     ).await?;
+    irc_client.set_control(control_tx);
     irc_client.connect().await?;
-    irc_client.verify_connected().await;
+    health.set_connected(irc_client.verify_connected().await);
 
-    // Initialize the API client
-    debug!("Initialize API client ...");
-    let api_handler = ApiHandler::new(
-        config.api.url,
-        config.api.token,
-    );
+    // Initialize the API clients
+    debug!("Initialize API clients ...");
+    let mut call_log = CallLog::open(&config.app.announced_file);
+    let mut pollers = open_pollers(&config.api, &config.app, &call_log)?;
+    for poller in &pollers {
+        info!("📡 Polling {} every {}s", poller.label(), poller.min_interval().as_secs());
+    }
+
+    // Single fetch and announce pass for cron-style deployments
+    if std::env::args().skip(1).any(|arg| arg == "--once") {
+        // A single fetch can't tell whether an item is still listed later
+        if announcer.pending.take().is_some() {
+            warn!("⚠️ app.announce_delay_secs is ignored with --once");
+        }
+        return run_once(&mut irc_client, &mut announcer, &pollers, &filter).await;
+    }
 
     // Main loop to keep the bot connected and fetch/post messages
     info!("✅ Application started");
-    let mut interval = interval(Duration::from_secs(2));
-    let mut last_api_call = Instant::now() - Duration::from_secs(30);
-    let mut connection_check = tokio::time::interval(Duration::from_secs(60));
+    let started = Instant::now();
+    let mut muted_until: Option<Instant> = None;
+    let mut quiet_hours = QuietHours::from_config(&config.app)?;
+    let mut quiet = false;
+    // Cleared by pause, the bot stays connected but nothing is fetched, announced or marked as seen
+    let announcing = irc_client.announcing();
+    let mut interval = interval(Duration::from_secs(config.app.poll_interval_secs));
+    // Fetches (including their retries) run in separate tasks so the IRC stream keeps being served.
+    // Results carry the poller generation, results of pollers replaced by a reload are dropped.
+    let (fetch_tx, mut fetch_rx) = mpsc::channel(pollers.len().max(1));
+    let mut generation = 0u64;
+    let mut connection_check = tokio::time::interval(Duration::from_secs(config.app.connection_check_secs));
+    let mut sigterm = signal(SignalKind::terminate())?;
 
     loop {
         tokio::select! {
-            Some(message) = irc_client.stream.next() => {
-                print!("{}", message?);
+            message = irc_client.stream.next() => {
+                match message {
+                    Some(Ok(message)) => {
+                        irc_client.handle_incoming(&message);
+                        // The server announced it's closing the link, don't wait for the socket to drop
+                        if irc_client.has_closing() {
+                            reconnect(&mut irc_client, &mut announcer, &health).await;
+                        }
+                    }
+                    Some(Err(e)) => {
+                        error!("❌ IRC connection error: {}", e);
+                        reconnect(&mut irc_client, &mut announcer, &health).await;
+                    }
+                    None => {
+                        warn!("❌ IRC connection closed");
+                        reconnect(&mut irc_client, &mut announcer, &health).await;
+                    }
+                }
             }
 
             _ = interval.tick() => {
                 let now = Instant::now();
 
-                // Only fetch if the rate limit allows
-                if now.duration_since(last_api_call) >= Duration::from_secs(30) {
-                    let messages = api_handler.fetch_messages().await;
+                // Hold everything back during quiet hours, the next fetch after them picks it up
+                let in_quiet_hours = quiet_hours.as_ref().is_some_and(|quiet_hours| quiet_hours.contains(Utc::now()));
+                if in_quiet_hours != quiet {
+                    quiet = in_quiet_hours;
+                    if quiet {
+                        info!("🌙 Quiet hours started, holding announcements");
+                    } else {
+                        info!("☀️ Quiet hours ended, resuming announcements");
+                        pollers.iter_mut().for_each(SourcePoller::fetch_now);
+                    }
+                }
+
+                let held = quiet || !announcing.load(Ordering::Relaxed) || muted_until.is_some_and(|until| now < until);
+                health.set_idle(held);
+                if held {
+                    continue;
+                }
+                announcer.release_held(&mut irc_client).await;
 
-                    for message in messages {
-                        if irc_client.should_announce(&message).await {
-                            let _ = irc_client.send_message(message).await;
-                        }
+                // Only fetch sources whose rate limit allows it
+                for (index, poller) in pollers.iter_mut().enumerate() {
+                    if !poller.is_due(now) {
+                        continue;
                     }
-                    // Update last successful API call time
-                    last_api_call = now;
+
+                    let fetch = poller.start();
+                    call_log.record(poller.key());
+                    let fetch_tx = fetch_tx.clone();
+                    tokio::spawn(async move {
+                        let _ = fetch_tx.send((generation, index, fetch.await)).await;
+                    });
                 }
-                else {
-                    debug!("Skipping API call to avoid rate limit");
+            }
+
+            Some((fetched_generation, index, result)) = fetch_rx.recv() => {
+                let Some(poller) = pollers.get_mut(index).filter(|_| fetched_generation == generation) else {
+                    debug!("Dropping the result of a fetch started before the last reload");
+                    continue;
+                };
+                poller.finished(&result);
+
+                let Ok(messages) = result else {
+                    continue;
+                };
+                health.fetched();
+                let source = poller.key().to_string();
+
+                // Nothing is marked as seen while muted, so these are picked up again later
+                if quiet || muted_until.is_some_and(|until| Instant::now() < until) {
+                    debug!("Announcements muted, dropping fetch result");
+                    continue;
                 }
+
+                announce(&mut irc_client, &mut announcer, &source, messages, &filter).await;
+            }
+
+            Some(ControlRequest { command, reply }) = control_rx.recv() => {
+                let response = match command {
+                    ControlCommand::Stats => {
+                        let muted = match muted_until {
+                            Some(until) if until > Instant::now() => format!("{}s", (until - Instant::now()).as_secs()),
+                            _ => "no".to_string(),
+                        };
+                        format!(
+                            "ok seen={} uptime={}s last_fetch={}s muted={} paused={}",
+                            announcer.seen.len().await,
+                            started.elapsed().as_secs(),
+                            pollers.iter().map(|poller| poller.last_call().elapsed().as_secs()).min().unwrap_or_default(),
+                            muted,
+                            if announcing.load(Ordering::Relaxed) { "no" } else { "yes" },
+                        )
+                    }
+                    ControlCommand::FetchNow => {
+                        pollers.iter_mut().for_each(SourcePoller::fetch_now);
+                        "ok fetching on next tick".to_string()
+                    }
+                    ControlCommand::Mute(0) | ControlCommand::Unmute => {
+                        info!("🔊 Announcements unmuted");
+                        muted_until = None;
+                        "ok unmuted".to_string()
+                    }
+                    ControlCommand::Pause => {
+                        if announcing.swap(false, Ordering::Relaxed) {
+                            info!("⏸️ Announcements paused");
+                        }
+                        "ok paused".to_string()
+                    }
+                    ControlCommand::Resume => {
+                        if !announcing.swap(true, Ordering::Relaxed) {
+                            info!("▶️ Announcements resumed");
+                            // Pick up whatever was held back right away
+                            pollers.iter_mut().for_each(SourcePoller::fetch_now);
+                        }
+                        "ok resumed".to_string()
+                    }
+                    ControlCommand::Mute(minutes) => match minutes.checked_mul(60)
+                        .and_then(|secs| Instant::now().checked_add(Duration::from_secs(secs)))
+                    {
+                        Some(until) => {
+                            info!("🔇 Announcements muted for {} minutes", minutes);
+                            muted_until = Some(until);
+                            format!("ok muted for {} minutes", minutes)
+                        }
+                        None => format!("error: {} minutes is out of range", minutes),
+                    },
+                    ControlCommand::Reload => match load_config(&config_path).map_err(|e| e.to_string()).and_then(|config| {
+                        let reloaded = open_pollers(&config.api, &config.app, &call_log)
+                            .map_err(|e| format!("Failed to create API client: {}", e))?;
+                        let reloaded_quiet_hours = QuietHours::from_config(&config.app).map_err(|e| e.to_string())?;
+                        Ok((config, reloaded, reloaded_quiet_hours))
+                    }) {
+                        Ok((config, reloaded, reloaded_quiet_hours)) => {
+                            info!("🔄 Configuration reloaded");
+                            pollers = reloaded;
+                            generation += 1;
+                            announcer.budget = config.app.budget.as_ref().map(AnnounceBudget::new);
+                            announcer.pending = config.app.announce_delay_secs.map(PendingReleases::new);
+                            announcer.category_limit = config.app.category_rate_limit.as_ref().map(CategoryLimiter::new);
+                            filter = config.filter;
+                            quiet_hours = reloaded_quiet_hours;
+                            "ok reloaded".to_string()
+                        }
+                        Err(e) => format!("error: {}", e),
+                    },
+                };
+                let _ = reply.send(response);
             }
 
-            // Connection verification (will crash on failure)
+            // Shut down cleanly on Ctrl-C or a container stop
+            _ = tokio::signal::ctrl_c() => {
+                info!("🛑 Received SIGINT, shutting down");
+                break;
+            }
+
+            _ = sigterm.recv() => {
+                info!("🛑 Received SIGTERM, shutting down");
+                break;
+            }
+
+            // Connection verification, reconnect on failure
             _ = connection_check.tick() => {
-                irc_client.verify_connected().await;
+                if !irc_client.verify_connected().await {
+                    reconnect(&mut irc_client, &mut announcer, &health).await;
+                }
+            }
+        }
+    }
+
+    irc_client.quit().await;
+    announcer.seen.flush().await;
+    info!("👋 Shutdown complete");
+    Ok(())
+}
+
+// The seen set and every output, IRC itself is passed in separately since the main
+// loop also needs it for the connection
+struct Announcer {
+    seen: SeenTracker,
+    notifiers: Vec<Box<dyn Notifier>>,
+    policy: NotifyPolicy,
+    // On a first run, only items bumped after startup are announced
+    skip_backlog: bool,
+    started_at: DateTime<Utc>,
+    dedup_by_name: bool,
+    // Combine up to this many new items of a fetch into one IRC message
+    batch_size: Option<usize>,
+    // New items are only announced once they have been listed for the announce delay
+    pending: Option<PendingReleases>,
+    // Announcements per category and time, items over the rate are left for a later fetch
+    category_limit: Option<CategoryLimiter>,
+    // Announcements per window, charged for every announcement IRC actually sent
+    budget: Option<AnnounceBudget>,
+}
+
+impl Announcer {
+    // Hand an item to IRC and every other notifier, then mark it as seen according
+    // to the notify policy. Returns false if any notifier failed.
+    async fn dispatch(&mut self, irc_client: &mut IrcClient, item: &Release) -> bool {
+        let irc_sent = match irc_client.notify(item).await {
+            Ok(()) => {
+                self.charge_budget(1);
+                true
+            }
+            Err(e) => {
+                warn!("⚠️ IRC failed to announce ID {}: {}", item.id, e);
+                false
+            }
+        };
+        self.notify_others(item, irc_sent).await
+    }
+
+    // Announce items in batches of batch_size on IRC, grouped by their channels. The
+    // other notifiers still get each item on its own.
+    async fn dispatch_batched(&mut self, irc_client: &mut IrcClient, items: Vec<Release>, batch_size: usize) -> bool {
+        let mut groups: Vec<(Vec<String>, Vec<Release>)> = Vec::new();
+        for item in items {
+            let targets = irc_client.route_targets(&item);
+            match groups.iter_mut().find(|(group_targets, _)| *group_targets == targets) {
+                Some((_, group)) => group.push(item),
+                None => groups.push((targets, vec![item])),
+            }
+        }
+
+        let mut all_sent = true;
+        for (targets, group) in groups {
+            for batch in group.chunks(batch_size) {
+                let irc_sent = match irc_client.send_batch(batch, targets.clone()).await {
+                    Ok(()) => {
+                        self.charge_budget(batch.len());
+                        true
+                    }
+                    Err(e) => {
+                        warn!("⚠️ IRC failed to announce a batch of {} items: {}", batch.len(), e);
+                        false
+                    }
+                };
+                for item in batch {
+                    all_sent &= self.notify_others(item, irc_sent).await;
+                }
+            }
+        }
+        all_sent
+    }
+
+    // Hand an item IRC already had to the other notifiers and settle its seen state
+    async fn notify_others(&mut self, item: &Release, irc_sent: bool) -> bool {
+        let (mut succeeded, mut failed) = if irc_sent { (1, 0) } else { (0, 1) };
+
+        for notifier in &mut self.notifiers {
+            match notifier.notify(item).await {
+                Ok(()) => succeeded += 1,
+                Err(e) => {
+                    warn!("⚠️ {} failed to announce ID {}: {}", notifier.name(), item.id, e);
+                    failed += 1;
+                }
+            }
+        }
+
+        if self.policy.should_mark(succeeded, failed) {
+            debug!("Marking ID {} as seen", item.id);
+            self.seen.mark(item).await;
+        } else {
+            warn!("Will not mark ID {} as seen", item.id);
+        }
+        failed == 0
+    }
+
+    // Announce what the announcements-per-minute cap held back and now allows
+    async fn release_held(&mut self, irc_client: &mut IrcClient) {
+        let released = irc_client.take_held();
+        if released.is_empty() {
+            return;
+        }
+
+        info!("🚦 Releasing {} held announcements, {} still held", released.len(), irc_client.held_len());
+        match self.batch_size {
+            Some(batch_size) => {
+                self.dispatch_batched(irc_client, released, batch_size).await;
+            }
+            None => {
+                for item in &released {
+                    self.dispatch(irc_client, item).await;
+                }
+            }
+        }
+    }
+
+    fn charge_budget(&mut self, sent: usize) {
+        if let Some(budget) = self.budget.as_mut() {
+            (0..sent).for_each(|_| budget.record());
+        }
+    }
+
+    // Resend IRC announcements that failed before a reconnect, the other notifiers already had theirs
+    async fn retry_failed(&mut self, irc_client: &mut IrcClient) {
+        let queued = irc_client.take_retries();
+        if queued.is_empty() {
+            return;
+        }
+
+        info!("🔁 Retrying {} failed announcements", queued.len());
+        for (item, targets) in queued {
+            // Not checked against the seen set, with the any policy the item is marked already
+            if irc_client.send_message(&item, targets).await.is_ok() {
+                self.charge_budget(1);
+                self.seen.mark(&item).await;
+            }
+        }
+    }
+}
+
+// Announce a fetched batch, returns false if any announcement failed to send
+async fn announce(
+    irc_client: &mut IrcClient,
+    announcer: &mut Announcer,
+    source: &str,
+    messages: Vec<Release>,
+    filter: &FilterConfig,
+) -> bool {
+    // Nothing is marked as seen while paused, so these are announced once resumed
+    if !irc_client.is_announcing() {
+        debug!("Announcements paused, leaving {} items for later", messages.len());
+        return true;
+    }
+
+    irc_client.note_fetch();
+    announcer.seen.note_api_window(source, &messages).await;
+    if let Some(pending) = announcer.pending.as_mut() {
+        pending.track(source, &messages);
+    }
+    // Announce oldest-first so the channel reads chronologically
+    let mut messages = sort_oldest_first(messages);
+    if announcer.dedup_by_name {
+        messages = dedup_by_name(messages);
+    }
+
+    // With a fresh announced file, don't dump the whole backlog into the channel,
+    // only items bumped after startup get announced
+    if announcer.seen.take_first_run(source) && announcer.skip_backlog {
+        let (fresh, backlog): (Vec<_>, Vec<_>) = messages.into_iter()
+            .partition(|message| parse_bumped_at(&message.attributes.bumped_at)
+                .is_ok_and(|bumped_at| bumped_at > announcer.started_at));
+        info!("⏭️ First run, marking {} backlog items as seen without announcing", backlog.len());
+        announcer.seen.mark_all(&backlog).await;
+        messages = fresh;
+    }
+    let mut suppressed = 0;
+    let mut throttled = 0;
+    let mut held = 0;
+    let mut all_sent = true;
+    let mut batched = Vec::new();
+
+    for mut message in messages {
+        if !passes_filters(&message, filter) {
+            debug!("⏭️ ID {} filtered out, skipping", message.id);
+            continue;
+        }
+
+        let state = announcer.seen.state(&message).await;
+        message.bumped = state == SeenState::Bumped;
+        if state != SeenState::Seen && !irc_client.is_held(&message) {
+            // Items no channel wants stay unmarked, in case the filters change
+            if irc_client.route_targets(&message).is_empty() {
+                debug!("⏭️ No channel filter matches ID {}, skipping", message.id);
+                continue;
+            }
+
+            // Left unmarked, a later fetch announces it if it's still listed by then
+            if announcer.pending.as_ref().is_some_and(|pending| !pending.is_settled(source, &message)) {
+                debug!("⏳ ID {} is waiting for the announce delay", message.id);
+                continue;
+            }
+
+            if let Some(limiter) = announcer.category_limit.as_mut() {
+                if !limiter.try_take(message.attributes.category.as_deref()) {
+                    debug!("Category rate limit reached, deferring ID {}", message.id);
+                    throttled += 1;
+                    continue;
+                }
+            }
+
+            // Enforce the announce budget for the current window, counting what's batched or held
+            let reserved = batched.len() + irc_client.held_len();
+            match announcer.budget.as_mut().map_or(Admission::Send, |budget| budget.admit(reserved)) {
+                Admission::Send => {}
+                Admission::Defer => {
+                    debug!("Announce budget exhausted, deferring ID {}", message.id);
+                    continue;
+                }
+                Admission::Suppress => {
+                    announcer.seen.mark(&message).await;
+                    suppressed += 1;
+                    continue;
+                }
+            }
+
+            // Over the announcements-per-minute cap, sent on a later tick
+            if irc_client.must_hold() {
+                irc_client.hold(message);
+                held += 1;
+                continue;
+            }
+
+            if announcer.batch_size.is_some() {
+                batched.push(message);
+            } else if !announcer.dispatch(irc_client, &message).await {
+                all_sent = false;
+            }
+        }
+    }
+
+    if let Some(batch_size) = announcer.batch_size.filter(|_| !batched.is_empty()) {
+        all_sent &= announcer.dispatch_batched(irc_client, batched, batch_size).await;
+    }
+
+    if throttled > 0 {
+        info!("🚦 Category rate limit reached, leaving {} items for a later fetch", throttled);
+    }
+
+    if held > 0 {
+        info!("🚦 Announcement cap reached, holding {} items ({} held in total)", held, irc_client.held_len());
+    }
+
+    if suppressed > 0 {
+        info!("🚫 Announce budget exhausted, suppressed {} items", suppressed);
+        let _ = irc_client.send_line(&format!("... and {} more suppressed", suppressed));
+    }
+
+    all_sent
+}
+
+// Fetch once while still serving the IRC stream, announce the result and quit
+async fn run_once(
+    irc_client: &mut IrcClient,
+    announcer: &mut Announcer,
+    pollers: &[SourcePoller],
+    filter: &FilterConfig,
+) -> Result<(), Error> {
+    let mut failed = Vec::new();
+
+    for poller in pollers {
+        let fetch = poller.fetch();
+        tokio::pin!(fetch);
+
+        let result = loop {
+            tokio::select! {
+                result = &mut fetch => break result,
+                message = irc_client.stream.next() => match message {
+                    Some(Ok(message)) => irc_client.handle_incoming(&message),
+                    Some(Err(e)) => return Err(e.into()),
+                    None => return Err(Error::Disconnected),
+                },
+            }
+        };
+
+        match result {
+            Ok(messages) => {
+                if !announce(irc_client, announcer, poller.key(), messages, filter).await {
+                    failed.push(format!("announcing from {}", poller.label()));
+                }
+            }
+            Err(e) => {
+                error!("❌ {} fetch failed: {}", poller.label(), e);
+                failed.push(format!("fetching {}", poller.label()));
             }
         }
     }
+
+    irc_client.quit().await;
+    if !failed.is_empty() {
+        return Err(Error::RunFailed(failed));
+    }
+    info!("✅ Single run finished");
+    Ok(())
+}
+
+// Keep reconnecting with exponential backoff until it succeeds
+async fn reconnect(irc_client: &mut IrcClient, announcer: &mut Announcer, health: &HealthState) {
+    let mut backoff = Duration::from_secs(1);
+    health.set_connected(false);
+
+    if let Some(delay) = irc_client.take_closing() {
+        info!("⏳ Reconnecting in {}s after the server closed the link", delay.as_secs());
+        tokio::time::sleep(delay).await;
+    }
+
+    loop {
+        match irc_client.reconnect().await {
+            Ok(_) => {
+                info!("✅ Reconnected");
+                health.set_connected(true);
+                announcer.retry_failed(irc_client).await;
+                return;
+            }
+            Err(e) => {
+                error!("❌ Reconnect failed: {}, retrying in {}s", irc_client::describe_error(&e), backoff.as_secs());
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+            }
+        }
+    }
+}
+
+// ANNOUNCARR_LOG_FORMAT overrides log_format, RUST_LOG overrides log_level (default info)
+fn init_logging(format: LogFormat, level: Option<&str>) {
+    let format = std::env::var("ANNOUNCARR_LOG_FORMAT").ok()
+        .and_then(|value| LogFormat::parse(&value))
+        .unwrap_or(format);
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(level.unwrap_or("info")));
+
+    match format {
+        LogFormat::Text => tracing_subscriber::fmt().with_env_filter(filter).init(),
+        LogFormat::Json => tracing_subscriber::fmt().json().with_ansi(false).with_env_filter(filter).init(),
+    }
+}
+
+// --config <path> wins over ANNOUNCARR_CONFIG, which wins over config.toml in the working directory
+fn config_path() -> Result<PathBuf, String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next()
+                .map(PathBuf::from)
+                .ok_or_else(|| "--config requires a path".to_string());
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Ok(PathBuf::from(path));
+        }
+    }
+
+    Ok(std::env::var_os("ANNOUNCARR_CONFIG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH)))
 }
@@ -0,0 +1,76 @@
+// Prometheus metrics over a minimal HTTP endpoint.
+//
+// The counters live in a process-wide static so the fetch, announce and reconnect
+// paths can update them without threading a handle through. When `metrics_addr`
+// is set, `GET /metrics` on that address returns them in the Prometheus text format.
+use std::fmt::Write as _;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::info;
+
+use crate::http::{self, Response};
+
+pub struct Metrics {
+    pub announcements_sent: AtomicU64,
+    pub api_fetch_success: AtomicU64,
+    pub api_fetch_failure: AtomicU64,
+    pub api_items_invalid: AtomicU64,
+    pub seen_items: AtomicU64,
+    pub reconnects: AtomicU64,
+    // Unix timestamp of the last successful API fetch, 0 before the first one
+    pub last_fetch_success: AtomicU64,
+}
+
+pub static METRICS: Metrics = Metrics {
+    announcements_sent: AtomicU64::new(0),
+    api_fetch_success: AtomicU64::new(0),
+    api_fetch_failure: AtomicU64::new(0),
+    api_items_invalid: AtomicU64::new(0),
+    seen_items: AtomicU64::new(0),
+    reconnects: AtomicU64::new(0),
+    last_fetch_success: AtomicU64::new(0),
+};
+
+impl Metrics {
+    pub fn inc(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set(gauge: &AtomicU64, value: u64) {
+        gauge.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(metric: &AtomicU64) -> u64 {
+        metric.load(Ordering::Relaxed)
+    }
+
+    fn render(&self) -> String {
+        let metrics = [
+            ("announcarr_announcements_sent_total", "counter", "Announcements sent to IRC", &self.announcements_sent),
+            ("announcarr_api_fetch_success_total", "counter", "Successful API fetches", &self.api_fetch_success),
+            ("announcarr_api_fetch_failure_total", "counter", "Failed API fetches, after retries", &self.api_fetch_failure),
+            ("announcarr_api_items_invalid_total", "counter", "API items skipped because they couldn't be parsed", &self.api_items_invalid),
+            ("announcarr_seen_items", "gauge", "Items in the seen set", &self.seen_items),
+            ("announcarr_reconnects_total", "counter", "IRC reconnects", &self.reconnects),
+            ("announcarr_last_fetch_success_timestamp_seconds", "gauge", "Unix time of the last successful API fetch", &self.last_fetch_success),
+        ];
+
+        let mut body = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(body, "# HELP {} {}", name, help);
+            let _ = writeln!(body, "# TYPE {} {}", name, kind);
+            let _ = writeln!(body, "{} {}", name, value.load(Ordering::Relaxed));
+        }
+        body
+    }
+}
+
+// Bind the metrics endpoint and serve it from a separate task
+pub async fn spawn(addr: &str) -> io::Result<()> {
+    http::serve(addr, |path| match path {
+        "/metrics" => Response::ok("text/plain; version=0.0.4", METRICS.render()),
+        _ => Response::not_found(),
+    }).await?;
+    info!("📈 Metrics listening on http://{}/metrics", addr);
+    Ok(())
+}
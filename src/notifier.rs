@@ -0,0 +1,48 @@
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use std::fmt;
+
+use crate::source::Release;
+
+#[derive(Debug)]
+pub enum NotifyError {
+    Irc(irc::error::Error),
+    // The message was sent but the connection check afterwards failed
+    NotConnected,
+    Http(reqwest::Error),
+    // The backend answered with a non-2xx status
+    Rejected(StatusCode),
+}
+
+impl fmt::Display for NotifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotifyError::Irc(e) => write!(f, "IRC error: {}", e),
+            NotifyError::NotConnected => write!(f, "not connected"),
+            NotifyError::Http(e) => write!(f, "HTTP error: {}", e),
+            NotifyError::Rejected(status) => write!(f, "rejected with HTTP {}", status),
+        }
+    }
+}
+
+impl std::error::Error for NotifyError {}
+
+impl From<irc::error::Error> for NotifyError {
+    fn from(e: irc::error::Error) -> Self {
+        NotifyError::Irc(e)
+    }
+}
+
+impl From<reqwest::Error> for NotifyError {
+    fn from(e: reqwest::Error) -> Self {
+        NotifyError::Http(e)
+    }
+}
+
+// An output backend announcements are sent to. Marking items as seen is up to the
+// caller, once every notifier has had its chance.
+#[async_trait]
+pub trait Notifier: Send {
+    fn name(&self) -> &str;
+    async fn notify(&mut self, item: &Release) -> Result<(), NotifyError>;
+}
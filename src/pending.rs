@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+use tokio::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::source::Release;
+
+// When each listed release was first fetched, per source and ID, so it's only announced
+// once the delay passed. Trackers sometimes delete or re-upload a release within its first minute.
+pub struct PendingReleases {
+    delay: Duration,
+    pending: HashMap<String, HashMap<String, Instant>>,
+}
+
+impl PendingReleases {
+    pub fn new(delay_secs: u64) -> Self {
+        Self {
+            delay: Duration::from_secs(delay_secs),
+            pending: HashMap::new(),
+        }
+    }
+
+    // Note a source's fetched items and forget those no longer listed
+    pub fn track(&mut self, source: &str, fetched: &[Release]) {
+        let now = Instant::now();
+        let pending = self.pending.entry(source.to_string()).or_default();
+
+        pending.retain(|id, first_seen| {
+            let listed = fetched.iter().any(|item| item.id == *id);
+            if !listed && now.duration_since(*first_seen) < self.delay {
+                debug!("🗑️ ID {} disappeared before its announce delay passed, dropping it", id);
+            }
+            listed
+        });
+        for item in fetched {
+            pending.entry(item.id.clone()).or_insert(now);
+        }
+    }
+
+    // Whether an item has been listed for at least the announce delay
+    pub fn is_settled(&self, source: &str, item: &Release) -> bool {
+        self.pending.get(source)
+            .and_then(|pending| pending.get(&item.id))
+            .is_some_and(|first_seen| first_seen.elapsed() >= self.delay)
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+use crate::config::{ApiConfig, AppConfig, ConfigError, ProxyConfig};
+use crate::error::Error;
+use crate::source::{open_source, Release, Source, SourceError};
+
+// Consecutive failed fetches before warning that a source looks down
+const FAILURE_ALERT_THRESHOLD: u32 = 3;
+
+// One configured source and its own rate limit
+pub struct SourcePoller {
+    source: Arc<dyn Source>,
+    name: Option<String>,
+    url_template: Option<String>,
+    min_interval: Duration,
+    last_call: Instant,
+    in_flight: bool,
+    failures: u32,
+}
+
+impl SourcePoller {
+    fn open(config: ApiConfig, default_interval_secs: u64, proxy: Option<&ProxyConfig>, call_log: &CallLog) -> Result<Self, SourceError> {
+        let min_interval = Duration::from_secs(config.min_api_interval_secs.unwrap_or(default_interval_secs));
+        let name = config.name.clone();
+        let url_template = config.download_url_template.clone();
+
+        // A call shortly before a restart still counts against the rate limit
+        let since_last_call = call_log.elapsed(name.as_deref().unwrap_or_default())
+            .map_or(min_interval, |elapsed| elapsed.min(min_interval));
+
+        let poller = Self {
+            source: open_source(config, proxy)?,
+            name,
+            url_template,
+            min_interval,
+            // Shortly after boot the monotonic clock may not reach back that far
+            last_call: Instant::now().checked_sub(since_last_call).unwrap_or_else(Instant::now),
+            in_flight: false,
+            failures: 0,
+        };
+        if since_last_call < min_interval {
+            info!("⏳ {} was fetched {}s ago, waiting {}s before fetching again",
+                poller.label(), since_last_call.as_secs(), (min_interval - since_last_call).as_secs());
+        }
+        Ok(poller)
+    }
+
+    // The configured name, or the kind of source for an unnamed one
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(self.source.name())
+    }
+
+    // Key for per-source state, empty for an unnamed source
+    pub fn key(&self) -> &str {
+        self.name.as_deref().unwrap_or_default()
+    }
+
+    pub fn min_interval(&self) -> Duration {
+        self.min_interval
+    }
+
+    pub fn last_call(&self) -> Instant {
+        self.last_call
+    }
+
+    // A fetch is due once the rate limit allows it and the previous one finished
+    pub fn is_due(&self, now: Instant) -> bool {
+        !self.in_flight && now.duration_since(self.last_call) >= self.min_interval
+    }
+
+    pub fn fetch_now(&mut self) {
+        self.last_call = Instant::now().checked_sub(self.min_interval).unwrap_or_else(Instant::now);
+    }
+
+    // Start a fetch, failed ones count against the rate limit too
+    pub fn start(&mut self) -> impl std::future::Future<Output = Result<Vec<Release>, SourceError>> + Send + 'static {
+        self.last_call = Instant::now();
+        self.in_flight = true;
+        fetch_tagged(Arc::clone(&self.source), self.name.clone(), self.url_template.clone())
+    }
+
+    // Fetch right away, ignoring the rate limit
+    pub async fn fetch(&self) -> Result<Vec<Release>, SourceError> {
+        fetch_tagged(Arc::clone(&self.source), self.name.clone(), self.url_template.clone()).await
+    }
+
+    pub fn finished(&mut self, result: &Result<Vec<Release>, SourceError>) {
+        self.in_flight = false;
+
+        let e = match result {
+            Ok(_) => {
+                self.failures = 0;
+                return;
+            }
+            Err(e) => e,
+        };
+        self.failures += 1;
+        error!("❌ {} fetch failed: {}", self.label(), e);
+
+        // Hold off the next fetch for as long as the source asked us to
+        if let Some(retry_after) = e.retry_after() {
+            info!("⏸️ {} asked to retry after {}s", self.label(), retry_after.as_secs());
+            // Near boot the subtraction can underflow, then the regular interval applies
+            self.last_call = Instant::now().checked_add(retry_after)
+                .and_then(|next_call| next_call.checked_sub(self.min_interval))
+                .unwrap_or_else(Instant::now);
+        }
+
+        if self.failures >= FAILURE_ALERT_THRESHOLD {
+            warn!("⚠️ {} fetch failed {} times in a row", self.label(), self.failures);
+        }
+    }
+}
+
+async fn fetch_tagged(source: Arc<dyn Source>, name: Option<String>, url_template: Option<String>) -> Result<Vec<Release>, SourceError> {
+    let mut releases = source.fetch().await?;
+    for release in &mut releases {
+        release.source = name.clone();
+        release.url_template = url_template.clone();
+    }
+    Ok(releases)
+}
+
+pub fn open_pollers(api: &[ApiConfig], app: &AppConfig, call_log: &CallLog) -> Result<Vec<SourcePoller>, Error> {
+    let proxy = app.proxy.as_ref()
+        .map(|proxy| ProxyConfig::parse(proxy.expose()))
+        .transpose()
+        .map_err(|e| ConfigError::Invalid("app.proxy".to_string(), e))?;
+    let pollers = api.iter()
+        .map(|api| SourcePoller::open(api.clone(), app.min_api_interval_secs, proxy.as_ref(), call_log))
+        .collect::<Result<_, _>>()?;
+    Ok(pollers)
+}
+
+// Unix time of each source's last API call, keyed like the seen IDs. Instants don't
+// survive a restart, so this is kept in a small file next to the announced file to
+// stop restart loops from hammering the API past its rate limit.
+pub struct CallLog {
+    path: PathBuf,
+    calls: HashMap<String, u64>,
+}
+
+impl CallLog {
+    // A missing or unreadable file just means no call is known
+    pub fn open(announced_file: &str) -> Self {
+        let path = Path::new(announced_file).with_extension("state.json");
+        let calls = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("⚠️ Ignoring unreadable state file {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+
+        Self { path, calls }
+    }
+
+    // A timestamp in the future (the clock went back) counts as just now
+    fn elapsed(&self, key: &str) -> Option<Duration> {
+        let call = SystemTime::UNIX_EPOCH + Duration::from_secs(*self.calls.get(key)?);
+        Some(SystemTime::now().duration_since(call).unwrap_or_default())
+    }
+
+    pub fn record(&mut self, key: &str) {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+        self.calls.insert(key.to_string(), now.as_secs());
+
+        debug!("Saving last API call to {} ...", self.path.display());
+        let result = serde_json::to_string(&self.calls)
+            .map_err(std::io::Error::from)
+            .and_then(|content| std::fs::write(&self.path, content));
+        if let Err(e) = result {
+            warn!("⚠️ Failed to write state file {}: {}", self.path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use chrono::{DateTime, Local, NaiveTime, Utc};
+use chrono_tz::Tz;
+
+use crate::config::{AppConfig, ConfigError};
+
+// Daily window in which nothing is announced. Items are not marked as seen
+// meanwhile, so they're announced once the window ends.
+#[derive(Debug, Clone)]
+pub struct QuietHours {
+    start: NaiveTime,
+    end: NaiveTime,
+    // None uses the system's local timezone
+    timezone: Option<Tz>,
+}
+
+impl QuietHours {
+    pub fn from_config(app: &AppConfig) -> Result<Option<Self>, ConfigError> {
+        let (start, end) = match (&app.quiet_hours_start, &app.quiet_hours_end) {
+            (Some(start), Some(end)) => (start, end),
+            (None, None) => return Ok(None),
+            (None, Some(_)) => return Err(invalid("app.quiet_hours_start", "must be set together with quiet_hours_end")),
+            (Some(_), None) => return Err(invalid("app.quiet_hours_end", "must be set together with quiet_hours_start")),
+        };
+
+        let timezone = match &app.quiet_hours_timezone {
+            Some(name) => Some(name.parse::<Tz>()
+                .map_err(|_| invalid("app.quiet_hours_timezone", format!("unknown timezone '{}'", name)))?),
+            None => None,
+        };
+
+        Ok(Some(Self {
+            start: parse_time("app.quiet_hours_start", start)?,
+            end: parse_time("app.quiet_hours_end", end)?,
+            timezone,
+        }))
+    }
+
+    // The end is exclusive, a window like 23:00-07:00 wraps around midnight
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        let time = match self.timezone {
+            Some(timezone) => now.with_timezone(&timezone).time(),
+            None => now.with_timezone(&Local).time(),
+        };
+
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+fn parse_time(field: &str, value: &str) -> Result<NaiveTime, ConfigError> {
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .map_err(|_| invalid(field, format!("invalid time '{}', expected HH:MM", value)))
+}
+
+fn invalid(field: &str, reason: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid(field.to_string(), reason.into())
+}
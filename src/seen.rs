@@ -0,0 +1,192 @@
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use tracing::{debug, error, info};
+
+use crate::config::{AppConfig, DedupMode};
+use crate::metrics::{Metrics, METRICS};
+use crate::store::{AnnounceStore, SeenItem};
+use crate::source::Release;
+use crate::web_api::parse_bumped_at;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeenState {
+    New,
+    // Announced before with a different bumped_at
+    Bumped,
+    Seen,
+}
+
+// Which items have been announced already, shared by all notifiers
+pub struct SeenTracker {
+    store: Box<dyn AnnounceStore>,
+    dedup_mode: DedupMode,
+    rebump_cooldown: Option<chrono::Duration>,
+    seen_retention: Option<chrono::Duration>,
+    max_seen_entries: Option<usize>,
+    // bumped_at of the oldest item in each source's last response, nothing newer may be pruned
+    window_start: HashMap<String, DateTime<Utc>>,
+    // Nothing had been announced before this run (missing or empty announced file)
+    first_run: bool,
+    // Sources whose first poll has been handled already
+    polled: HashSet<String>,
+}
+
+impl SeenTracker {
+    pub async fn new(store: Box<dyn AnnounceStore>, app: &AppConfig) -> Self {
+        let seen_retention = app.seen_retention_days.map(|days| chrono::Duration::days(days as i64));
+
+        // Retention pruning waits for the first fetch, before it nothing says which old
+        // items the API still lists
+        if let Some(max_seen_entries) = app.max_seen_entries {
+            if let Err(e) = store.evict_oldest(max_seen_entries).await {
+                error!("Failed to evict seen IDs: {}", e);
+            }
+            if let Err(e) = store.flush().await {
+                error!("Failed to save seen IDs: {}", e);
+            }
+        }
+
+        let first_run = match store.load_all().await {
+            Ok(seen_items) => {
+                info!("Loaded {} seen IDs", seen_items.len());
+                Metrics::set(&METRICS.seen_items, seen_items.len() as u64);
+                seen_items.is_empty()
+            }
+            Err(e) => {
+                error!("Failed to load seen IDs: {}", e);
+                true
+            }
+        };
+
+        Self {
+            store,
+            dedup_mode: app.dedup_mode,
+            rebump_cooldown: app.rebump_cooldown_minutes.map(|minutes| chrono::Duration::minutes(minutes as i64)),
+            seen_retention,
+            max_seen_entries: app.max_seen_entries,
+            window_start: HashMap::new(),
+            first_run,
+            polled: HashSet::new(),
+        }
+    }
+
+    // Remember how far back a source currently reaches, so pruning never drops an item still
+    // listed, then prune with that window
+    pub async fn note_api_window(&mut self, source: &str, items: &[Release]) {
+        let window_start = items.iter()
+            .filter_map(|item| parse_bumped_at(&item.attributes.bumped_at).ok())
+            .min();
+        match window_start {
+            Some(window_start) => self.window_start.insert(source.to_string(), window_start),
+            None => self.window_start.remove(source),
+        };
+        self.prune().await;
+    }
+
+    // Returns whether this is a source's first poll of a first run, once per source
+    pub fn take_first_run(&mut self, source: &str) -> bool {
+        self.first_run && self.polled.insert(source.to_string())
+    }
+
+    pub async fn len(&self) -> usize {
+        self.store.len().await
+    }
+
+    pub async fn state(&self, item: &Release) -> SeenState {
+        let seen_item = seen_item(item);
+
+        // Only an exact match (same ID and timestamp) counts as announced, a changed
+        // timestamp means the item was bumped and replaces the old entry once announced.
+        // With id_only any entry for the ID counts.
+        match self.store.get(&seen_item.id).await {
+            Some(stored) if stored.bumped_at == seen_item.bumped_at || self.dedup_mode == DedupMode::IdOnly => {
+                debug!("⏭️ Already announced ID {}, skipping", seen_item.id);
+                SeenState::Seen
+            }
+            Some(stored) if self.in_rebump_cooldown(&stored) => {
+                debug!("⏭️ ID {} was bumped again within the cooldown, skipping for now", seen_item.id);
+                SeenState::Seen
+            }
+            Some(_) => SeenState::Bumped,
+            None => SeenState::New,
+        }
+    }
+
+    // Entries without announced_at (older seen files) are past any cooldown
+    fn in_rebump_cooldown(&self, stored: &SeenItem) -> bool {
+        let Some(cooldown) = self.rebump_cooldown else {
+            return false;
+        };
+        stored.announced_at.as_deref()
+            .and_then(|announced_at| parse_bumped_at(announced_at).ok())
+            .is_some_and(|announced_at| Utc::now() - announced_at < cooldown)
+    }
+
+    pub async fn mark(&self, item: &Release) {
+        if let Err(e) = self.store.mark(seen_item(item)).await {
+            error!("Failed to save seen IDs: {}", e);
+        }
+        self.prune().await;
+    }
+
+    // Mark several items at once, writing the file only once
+    pub async fn mark_all(&self, items: &[Release]) {
+        if let Err(e) = self.store.mark_all(items.iter().map(seen_item).collect()).await {
+            error!("Failed to save seen IDs: {}", e);
+        }
+        self.prune().await;
+    }
+
+    pub async fn flush(&self) {
+        if let Err(e) = self.store.flush().await {
+            error!("Failed to save seen IDs: {}", e);
+        }
+    }
+
+    // Also saves whatever was marked before, so a mark and its pruning are a single write
+    async fn prune(&self) {
+        self.prune_expired().await;
+        self.evict_over_cap().await;
+        self.flush().await;
+        Metrics::set(&METRICS.seen_items, self.store.len().await as u64);
+    }
+
+    async fn prune_expired(&self) {
+        if let Some(retention) = self.seen_retention {
+            let mut cutoff = Utc::now() - retention;
+            if let Some(window_start) = self.window_start.values().min() {
+                cutoff = cutoff.min(*window_start);
+            }
+            match self.store.prune(cutoff).await {
+                Ok(0) => {}
+                Ok(pruned) => debug!("Pruned {} seen IDs bumped before {}", pruned, cutoff),
+                Err(e) => error!("Failed to prune seen IDs: {}", e),
+            }
+        }
+    }
+
+    // Unlike pruning this may drop items the API still lists, which then get announced again
+    async fn evict_over_cap(&self) {
+        if let Some(max_seen_entries) = self.max_seen_entries {
+            match self.store.evict_oldest(max_seen_entries).await {
+                Ok(0) => {}
+                Ok(evicted) => debug!("Evicted the {} oldest seen IDs over max_seen_entries", evicted),
+                Err(e) => error!("Failed to evict seen IDs: {}", e),
+            }
+        }
+    }
+}
+
+// IDs of named sources are prefixed with the name, so the same ID on two sites doesn't collide
+fn seen_item(item: &Release) -> SeenItem {
+    let id = match &item.source {
+        Some(source) => format!("{}:{}", source, item.id),
+        None => item.id.clone(),
+    };
+
+    SeenItem {
+        id,
+        bumped_at: item.attributes.bumped_at.clone(),
+        announced_at: Some(Utc::now().to_rfc3339()),
+    }
+}
@@ -0,0 +1,274 @@
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use tokio::time::Duration;
+
+use crate::config::{ApiConfig, ProxyConfig, SourceType};
+use crate::feed::RssSource;
+use crate::web_api::ApiHandler;
+
+// Where releases come from. Each backend maps its own response format onto Release,
+// so filtering, formatting and announcing don't care which one is configured.
+#[async_trait]
+pub trait Source: Send + Sync {
+    fn name(&self) -> &str;
+    async fn fetch(&self) -> Result<Vec<Release>, SourceError>;
+}
+
+pub fn open_source(config: ApiConfig, proxy: Option<&ProxyConfig>) -> Result<Arc<dyn Source>, SourceError> {
+    match config.source_type {
+        SourceType::Unit3d => Ok(Arc::new(ApiHandler::new(config, proxy)?)),
+        SourceType::Rss => Ok(Arc::new(RssSource::new(config, proxy)?)),
+    }
+}
+
+#[derive(Debug)]
+pub enum SourceError {
+    // The source rejected the credentials (401/403)
+    Auth(StatusCode),
+    // 429 Too Many Requests or 503 Service Unavailable, with the delay asked for in Retry-After
+    RetryLater(StatusCode, Option<Duration>),
+    // Any other non-2xx response
+    Status(StatusCode),
+    Network(reqwest::Error),
+    // The SOCKS proxy failed or refused, as opposed to the source itself
+    Proxy(reqwest::Error),
+    Parse(serde_json::Error),
+    // Neither valid RSS nor valid Atom
+    Feed(String),
+    // The HTTP client couldn't be set up, e.g. an unusable proxy or certificate
+    Client(reqwest::Error),
+    // The configured ca_cert couldn't be read
+    CaCert(String, std::io::Error),
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceError::Auth(status) => write!(f, "authentication failed ({})", status),
+            SourceError::RetryLater(status, Some(delay)) => write!(f, "HTTP {}, retry after {}s", status, delay.as_secs()),
+            SourceError::RetryLater(status, None) => write!(f, "HTTP {}", status),
+            SourceError::Status(status) => write!(f, "HTTP {}", status),
+            SourceError::Network(e) if e.is_timeout() => write!(f, "request timed out: {}", e),
+            SourceError::Network(e) => write!(f, "network error: {}", e),
+            SourceError::Proxy(e) => write!(f, "proxy error: {}", source_chain(e)),
+            SourceError::Parse(e) => write!(f, "failed to parse response: {}", e),
+            SourceError::Feed(e) => write!(f, "failed to parse feed: {}", e),
+            SourceError::Client(e) => write!(f, "failed to create HTTP client: {}", e),
+            SourceError::CaCert(path, e) => write!(f, "failed to read CA certificate {}: {}", path, e),
+        }
+    }
+}
+
+impl std::error::Error for SourceError {}
+
+impl SourceError {
+    // Auth and parse failures won't go away by asking again
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, SourceError::RetryLater(..) | SourceError::Network(_) | SourceError::Proxy(_))
+            || matches!(self, SourceError::Status(status) if status.is_server_error())
+    }
+
+    // The delay the API asked for before the next request, if any
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            SourceError::RetryLater(_, retry_after) => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+// reqwest reports SOCKS failures as connect errors with a "socks connect error" source
+pub fn network_error(e: reqwest::Error) -> SourceError {
+    if e.is_connect() && source_chain(&e).contains("socks connect error") {
+        SourceError::Proxy(e)
+    } else {
+        SourceError::Network(e)
+    }
+}
+
+fn source_chain(e: &dyn std::error::Error) -> String {
+    let mut chain = e.to_string();
+    let mut source = e.source();
+    while let Some(e) = source {
+        chain.push_str(": ");
+        chain.push_str(&e.to_string());
+        source = e.source();
+    }
+    chain
+}
+
+// A release as every source reports it, in the JSON:API shape used by UNIT3D
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Release {
+    pub id: String,
+    pub attributes: Attributes,
+    // The configured name of the source it came from, set after fetching
+    #[serde(skip)]
+    pub source: Option<String>,
+    // The source's download_url_template, if it has its own
+    #[serde(skip)]
+    pub url_template: Option<String>,
+    // Announced before with an older bumped_at
+    #[serde(skip)]
+    pub bumped: bool,
+}
+
+// Only the name, download link and bump time are required. Everything else is
+// parsed leniently, a missing or oddly typed field becomes None (or 0 for the size)
+// instead of failing the whole response.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Attributes {
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub category: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub r#type: Option<String>,
+    pub name: String,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub resolution: Option<String>,
+    #[serde(default, deserialize_with = "freeleech")]
+    pub freeleech: Option<Freeleech>,
+    #[serde(default, deserialize_with = "lenient_bool")]
+    pub internal: Option<bool>,
+    #[serde(default, deserialize_with = "lenient_bool")]
+    pub double_upload: Option<bool>,
+    // 0 when unknown
+    #[serde(default, deserialize_with = "lenient_u64")]
+    pub size: u64,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub uploader: Option<String>,
+    pub download_link: String,
+    pub bumped_at: String,
+    // External IDs, some trackers send numbers, others strings, 0 or "" mean unknown
+    #[serde(default, deserialize_with = "external_id")]
+    pub imdb_id: Option<String>,
+    #[serde(default, deserialize_with = "external_id")]
+    pub tmdb_id: Option<String>,
+    #[serde(default, deserialize_with = "external_id")]
+    pub info_hash: Option<String>,
+    // Any fields not modelled above, e.g. tracker-specific routing hints
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+// Strings and numbers as a trimmed string, anything else or "" as None
+fn lenient_string<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(value) => value.trim().to_string(),
+        serde_json::Value::Number(value) => value.to_string(),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(value).filter(|value| !value.is_empty()))
+}
+
+fn external_id<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(lenient_string(deserializer)?.filter(|value| value != "0"))
+}
+
+// true/false, 1/0 or their string forms, plus yes/no
+fn lenient_bool<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Bool(value) => return Ok(Some(value)),
+        serde_json::Value::Number(value) => value.to_string(),
+        serde_json::Value::String(value) => value.trim().to_ascii_lowercase(),
+        _ => return Ok(None),
+    };
+
+    Ok(match value.as_str() {
+        "1" | "true" | "yes" => Some(true),
+        "0" | "false" | "no" => Some(false),
+        _ => None,
+    })
+}
+
+// The download discount of a release
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Freeleech {
+    No,
+    // Between 1 and 99 percent off
+    Partial(u8),
+    Full,
+}
+
+impl Freeleech {
+    fn from_percent(percent: f64) -> Self {
+        match percent.round() {
+            p if p <= 0.0 => Freeleech::No,
+            p if p >= 100.0 => Freeleech::Full,
+            p => Freeleech::Partial(p as u8),
+        }
+    }
+
+    pub fn is_free(self) -> bool {
+        self != Freeleech::No
+    }
+}
+
+impl fmt::Display for Freeleech {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Freeleech::No => f.write_str("No"),
+            Freeleech::Partial(percent) => write!(f, "{}%", percent),
+            Freeleech::Full => f.write_str("100%"),
+        }
+    }
+}
+
+// Trackers disagree on the shape:
+//   true/false, "true"/"false", "yes"/"no"  -> 100% / No
+//   "100%", "25%", "0%"                     -> that percentage off
+//   100, 25, 0, "25"                        -> a bare number is a percentage too
+// Percentages are rounded and clamped to 0-100, anything else becomes None.
+fn freeleech<'de, D>(deserializer: D) -> Result<Option<Freeleech>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Bool(true) => return Ok(Some(Freeleech::Full)),
+        serde_json::Value::Bool(false) => return Ok(Some(Freeleech::No)),
+        serde_json::Value::Number(value) => return Ok(value.as_f64().map(Freeleech::from_percent)),
+        serde_json::Value::String(value) => value.trim().to_ascii_lowercase(),
+        _ => return Ok(None),
+    };
+
+    Ok(match value.as_str() {
+        "true" | "yes" => Some(Freeleech::Full),
+        "false" | "no" => Some(Freeleech::No),
+        value => value.trim_end_matches('%').trim().parse::<f64>().ok()
+            .filter(|percent| percent.is_finite())
+            .map(Freeleech::from_percent),
+    })
+}
+
+// Byte counts as a number or numeric string, anything else as 0
+fn lenient_u64<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Number(value) => value.as_u64().or_else(|| value.as_f64().map(|f| f.max(0.0) as u64)).unwrap_or(0),
+        serde_json::Value::String(value) => value.trim().parse().unwrap_or(0),
+        _ => 0,
+    })
+}
+
+impl Attributes {
+    pub fn extra_str(&self, field: &str) -> Option<&str> {
+        self.extra.get(field)
+            .and_then(|value| value.as_str())
+            .filter(|value| !value.is_empty())
+    }
+}
@@ -0,0 +1,573 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::config::{AppConfig, StoreBackend};
+use crate::web_api::parse_bumped_at;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Hash, Eq, PartialEq)]
+pub struct SeenItem {
+    pub id: String,
+    pub bumped_at: String,
+    // RFC 3339 time it was last announced, missing in files written by older versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub announced_at: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    Io(io::Error),
+    // The announced file isn't a JSON array of seen items
+    Json(serde_json::Error),
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Error),
+    // The sqlite store's announced_file holds neither a database nor a JSON list to import
+    #[cfg(feature = "sqlite")]
+    NotADatabase(String),
+    // store = "sqlite" in a build without the sqlite feature
+    #[cfg(not(feature = "sqlite"))]
+    SqliteDisabled,
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Io(e) => write!(f, "I/O error: {}", e),
+            StoreError::Json(e) => write!(f, "invalid JSON: {}", e),
+            #[cfg(feature = "sqlite")]
+            StoreError::Sqlite(e) => write!(f, "SQLite error: {}", e),
+            #[cfg(feature = "sqlite")]
+            StoreError::NotADatabase(path) => write!(f, "{} is neither an SQLite database nor a JSON seen list", path),
+            #[cfg(not(feature = "sqlite"))]
+            StoreError::SqliteDisabled => write!(f, "the sqlite store requires building with --features sqlite"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(e: serde_json::Error) -> Self {
+        StoreError::Json(e)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        StoreError::Sqlite(e)
+    }
+}
+
+pub type StoreResult<T> = Result<T, StoreError>;
+
+// Persistence for the IDs that have already been announced. Implementations handle
+// their own locking, so a store can be shared behind a plain reference. Changes may
+// be held in memory until the next flush.
+#[async_trait]
+pub trait AnnounceStore: Send + Sync {
+    async fn load_all(&self) -> StoreResult<Vec<SeenItem>>;
+    // The stored entry for an ID, whatever its timestamp
+    async fn get(&self, id: &str) -> Option<SeenItem>;
+    // Marking an item replaces any entry with the same ID
+    async fn mark(&self, item: SeenItem) -> StoreResult<()> {
+        self.mark_all(vec![item]).await
+    }
+    async fn mark_all(&self, items: Vec<SeenItem>) -> StoreResult<()>;
+    // Drop entries bumped before the cutoff, entries with an unparsable timestamp are kept
+    async fn prune(&self, before: DateTime<Utc>) -> StoreResult<usize>;
+    // Keep only the most recently bumped entries, unparsable timestamps go first
+    async fn evict_oldest(&self, keep: usize) -> StoreResult<usize>;
+    async fn len(&self) -> usize;
+    // Make sure everything marked so far is on disk
+    async fn flush(&self) -> StoreResult<()> {
+        Ok(())
+    }
+}
+
+pub fn open_store(app: &AppConfig) -> StoreResult<Box<dyn AnnounceStore>> {
+    match app.store {
+        StoreBackend::Json => Ok(Box::new(JsonStore::open(&app.announced_file)?)),
+        #[cfg(feature = "sqlite")]
+        StoreBackend::Sqlite => Ok(Box::new(SqliteStore::open(&app.announced_file)?)),
+        #[cfg(not(feature = "sqlite"))]
+        StoreBackend::Sqlite => Err(StoreError::SqliteDisabled),
+    }
+}
+
+// The whole seen set as a JSON array, rewritten on flush when anything changed
+pub struct JsonStore {
+    announced_file: String,
+    seen: Mutex<JsonSeen>,
+}
+
+// Seen items by ID, and whether they differ from the file
+#[derive(Default)]
+struct JsonSeen {
+    items: HashMap<String, SeenItem>,
+    dirty: bool,
+}
+
+impl JsonStore {
+    pub fn open(announced_file: &str) -> StoreResult<Self> {
+        let seen = Self::load_seen_ids(announced_file)?;
+
+        Ok(Self {
+            announced_file: announced_file.to_string(),
+            seen: Mutex::new(JsonSeen {
+                items: seen.into_iter().map(|item| (item.id.clone(), item)).collect(),
+                dirty: false,
+            }),
+        })
+    }
+
+    fn load_seen_ids(announced_file: &str) -> StoreResult<Vec<SeenItem>> {
+        debug!("Load seen list from file ...");
+        if !Path::new(announced_file).exists() {
+            Self::write_seen_file(announced_file, &[])?;
+            return Ok(vec![]);
+        }
+
+        let error = match Self::read_seen_file(announced_file) {
+            Ok(seen_items) => return Ok(seen_items),
+            Err(e) => e,
+        };
+        warn!("⚠️ Announced file {} is unreadable: {}", announced_file, error);
+
+        // Keep the bad file around so operators can inspect or restore it
+        let corrupt_file = format!("{}.corrupt.{}", announced_file, Utc::now().format("%Y%m%d%H%M%S"));
+        fs::rename(announced_file, &corrupt_file)?;
+        warn!("⚠️ Moved unreadable announced file to {}", corrupt_file);
+
+        let backup_file = format!("{}.bak", announced_file);
+        let seen_items = match Self::read_seen_file(&backup_file) {
+            Ok(seen_items) => {
+                warn!("♻️ Recovered {} seen IDs from {}", seen_items.len(), backup_file);
+                seen_items
+            }
+            Err(e) => {
+                warn!("⚠️ Backup {} is unreadable too ({}), starting with an empty seen list", backup_file, e);
+                vec![]
+            }
+        };
+
+        Self::write_seen_file(announced_file, &seen_items)?;
+        Ok(seen_items)
+    }
+
+    fn read_seen_file(path: &str) -> StoreResult<Vec<SeenItem>> {
+        let file_content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&file_content)?)
+    }
+
+    fn save_seen_ids(&self, seen: &HashMap<String, SeenItem>) -> StoreResult<()> {
+        debug!("Saving ID & timestamp to file ...");
+        let seen_items: Vec<SeenItem> = seen.values().cloned().collect();
+        Self::write_seen_file(&self.announced_file, &seen_items)
+    }
+
+    // Write to a temporary file and rename it over the real one, so a crash mid-write
+    // never leaves a truncated file behind. The previous file is kept as <file>.bak.
+    fn write_seen_file(announced_file: &str, seen_items: &[SeenItem]) -> StoreResult<()> {
+        let tmp_file = format!("{}.tmp", announced_file);
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp_file)?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, seen_items)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+
+        if Path::new(announced_file).exists() {
+            fs::copy(announced_file, format!("{}.bak", announced_file))?;
+        }
+        fs::rename(&tmp_file, announced_file)?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AnnounceStore for JsonStore {
+    async fn load_all(&self) -> StoreResult<Vec<SeenItem>> {
+        Ok(self.seen.lock().await.items.values().cloned().collect())
+    }
+
+    async fn get(&self, id: &str) -> Option<SeenItem> {
+        self.seen.lock().await.items.get(id).cloned()
+    }
+
+    async fn mark_all(&self, items: Vec<SeenItem>) -> StoreResult<()> {
+        let mut seen = self.seen.lock().await;
+        for item in items {
+            seen.items.insert(item.id.clone(), item);
+            seen.dirty = true;
+        }
+        Ok(())
+    }
+
+    async fn prune(&self, before: DateTime<Utc>) -> StoreResult<usize> {
+        let mut seen = self.seen.lock().await;
+        let count = seen.items.len();
+        seen.items.retain(|_, item| parse_bumped_at(&item.bumped_at).map_or(true, |bumped_at| bumped_at >= before));
+
+        let pruned = count - seen.items.len();
+        seen.dirty |= pruned > 0;
+        Ok(pruned)
+    }
+
+    async fn evict_oldest(&self, keep: usize) -> StoreResult<usize> {
+        let mut seen = self.seen.lock().await;
+        if seen.items.len() <= keep {
+            return Ok(0);
+        }
+
+        let mut items: Vec<SeenItem> = seen.items.drain().map(|(_, item)| item).collect();
+        items.sort_by_key(|item| std::cmp::Reverse(parse_bumped_at(&item.bumped_at).ok()));
+        let evicted = items.split_off(keep).len();
+        seen.items.extend(items.into_iter().map(|item| (item.id.clone(), item)));
+        seen.dirty = true;
+        Ok(evicted)
+    }
+
+    async fn len(&self) -> usize {
+        self.seen.lock().await.items.len()
+    }
+
+    async fn flush(&self) -> StoreResult<()> {
+        let mut seen = self.seen.lock().await;
+        if seen.dirty {
+            self.save_seen_ids(&seen.items)?;
+            seen.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+// One row per announced ID, so marking an item is a single-row write
+#[cfg(feature = "sqlite")]
+pub struct SqliteStore {
+    connection: std::sync::Mutex<rusqlite::Connection>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStore {
+    pub fn open(path: &str) -> StoreResult<Self> {
+        // Switching from the json store, take the seen list along
+        if Self::holds_json(path)? {
+            Self::import_json(path)?;
+        }
+
+        let connection = Self::connect(path)?;
+        tracing::info!("🗄️ Using SQLite store {}", path);
+
+        Ok(Self {
+            connection: std::sync::Mutex::new(connection),
+        })
+    }
+
+    fn connect(path: &str) -> StoreResult<rusqlite::Connection> {
+        let connection = rusqlite::Connection::open(path)?;
+        // bumped_ts is the parsed bumped_at as unix seconds, NULL when it can't be parsed
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS seen (
+                id TEXT PRIMARY KEY,
+                bumped_at TEXT NOT NULL,
+                bumped_ts INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS seen_bumped_ts ON seen (bumped_ts);",
+        )?;
+        // Databases created before announced_at was tracked lack the column
+        let has_announced_at = connection
+            .prepare("SELECT 1 FROM pragma_table_info('seen') WHERE name = 'announced_at'")?
+            .exists([])?;
+        if !has_announced_at {
+            connection.execute_batch("ALTER TABLE seen ADD COLUMN announced_at TEXT;")?;
+        }
+        Ok(connection)
+    }
+
+    // Anything but a missing or empty file or an SQLite database is taken for a JSON seen list
+    fn holds_json(path: &str) -> StoreResult<bool> {
+        use std::io::Read;
+
+        let mut header = Vec::new();
+        match fs::File::open(path) {
+            Ok(file) => file.take(16).read_to_end(&mut header)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(!header.is_empty() && !b"SQLite format 3\0".starts_with(&header))
+    }
+
+    // Build the database next to the JSON file and only replace it once it's complete. The
+    // JSON file is kept as <file>.json.
+    fn import_json(path: &str) -> StoreResult<()> {
+        let seen_items = JsonStore::read_seen_file(path).map_err(|_| StoreError::NotADatabase(path.to_string()))?;
+
+        let import_path = format!("{}.import", path);
+        match fs::remove_file(&import_path) {
+            Ok(()) => warn!("⚠️ Discarded {} left over from an interrupted import", import_path),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        let mut connection = Self::connect(&import_path)?;
+        Self::insert(&mut connection, &seen_items)?;
+        drop(connection);
+
+        let json_file = format!("{}.json", path);
+        fs::copy(path, &json_file)?;
+        fs::rename(&import_path, path)?;
+        tracing::info!("🗄️ Imported {} seen IDs from {}, the JSON file is kept as {}", seen_items.len(), path, json_file);
+        Ok(())
+    }
+
+    fn insert(connection: &mut rusqlite::Connection, items: &[SeenItem]) -> StoreResult<()> {
+        let transaction = connection.transaction()?;
+        for item in items {
+            let bumped_ts = parse_bumped_at(&item.bumped_at).ok().map(|bumped_at| bumped_at.timestamp());
+            transaction.execute(
+                "INSERT OR REPLACE INTO seen (id, bumped_at, bumped_ts, announced_at) VALUES (?1, ?2, ?3, ?4)",
+                (&item.id, &item.bumped_at, bumped_ts, &item.announced_at),
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, rusqlite::Connection> {
+        // A panic while holding the lock can't leave the connection itself in a bad state
+        self.connection.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl AnnounceStore for SqliteStore {
+    async fn load_all(&self) -> StoreResult<Vec<SeenItem>> {
+        let connection = self.connection();
+        let mut statement = connection.prepare("SELECT id, bumped_at, announced_at FROM seen")?;
+        let rows = statement.query_map([], |row| {
+            Ok(SeenItem {
+                id: row.get(0)?,
+                bumped_at: row.get(1)?,
+                announced_at: row.get(2)?,
+            })
+        })?;
+
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    async fn get(&self, id: &str) -> Option<SeenItem> {
+        let result = self.connection().query_row(
+            "SELECT id, bumped_at, announced_at FROM seen WHERE id = ?1",
+            [id],
+            |row| Ok(SeenItem {
+                id: row.get(0)?,
+                bumped_at: row.get(1)?,
+                announced_at: row.get(2)?,
+            }),
+        );
+        match result {
+            Ok(item) => Some(item),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(e) => {
+                warn!("Failed to query SQLite store: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn mark_all(&self, items: Vec<SeenItem>) -> StoreResult<()> {
+        Self::insert(&mut self.connection(), &items)
+    }
+
+    async fn prune(&self, before: DateTime<Utc>) -> StoreResult<usize> {
+        Ok(self.connection().execute("DELETE FROM seen WHERE bumped_ts < ?1", [before.timestamp()])?)
+    }
+
+    async fn evict_oldest(&self, keep: usize) -> StoreResult<usize> {
+        // NULLs sort last when descending, so unparsable timestamps are evicted first
+        Ok(self.connection().execute(
+            "DELETE FROM seen WHERE id NOT IN (SELECT id FROM seen ORDER BY bumped_ts DESC LIMIT ?1)",
+            [keep as i64],
+        )?)
+    }
+
+    async fn len(&self) -> usize {
+        self.connection()
+            .query_row("SELECT COUNT(*) FROM seen", [], |row| row.get::<_, i64>(0))
+            .map_or(0, |count| count as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: &str) -> SeenItem {
+        SeenItem { id: id.to_string(), bumped_at: "2024-01-01T12:00:00Z".to_string(), announced_at: None }
+    }
+
+    fn ids(items: Vec<SeenItem>) -> Vec<String> {
+        let mut ids: Vec<String> = items.into_iter().map(|item| item.id).collect();
+        ids.sort();
+        ids
+    }
+
+    // Files next to the announced file that it was moved aside to
+    fn corrupt_files(dir: &Path) -> Vec<String> {
+        fs::read_dir(dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.starts_with("announced.json.corrupt."))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn missing_file_starts_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("announced.json");
+        let store = JsonStore::open(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(store.len().await, 0);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[]");
+    }
+
+    #[tokio::test]
+    async fn saving_keeps_the_previous_file_as_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("announced.json");
+        let store = JsonStore::open(path.to_str().unwrap()).unwrap();
+        store.mark(item("1")).await.unwrap();
+        store.flush().await.unwrap();
+        store.mark(item("2")).await.unwrap();
+        store.flush().await.unwrap();
+
+        let backup = JsonStore::read_seen_file(&format!("{}.bak", path.display())).unwrap();
+        assert_eq!(ids(backup), ["1"]);
+    }
+
+    #[tokio::test]
+    async fn changes_are_written_once_on_flush() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("announced.json");
+        let store = JsonStore::open(path.to_str().unwrap()).unwrap();
+
+        let mut old = item("1");
+        old.bumped_at = "2020-01-01T12:00:00Z".to_string();
+        store.mark_all(vec![old, item("2"), item("3")]).await.unwrap();
+        store.mark(SeenItem { bumped_at: "2024-02-01T12:00:00Z".to_string(), ..item("3") }).await.unwrap();
+        assert_eq!(store.prune("2021-01-01T00:00:00Z".parse().unwrap()).await.unwrap(), 1);
+        assert_eq!(store.evict_oldest(1).await.unwrap(), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[]");
+
+        store.flush().await.unwrap();
+        let saved = JsonStore::read_seen_file(path.to_str().unwrap()).unwrap();
+        assert_eq!(ids(saved.clone()), ["3"]);
+        assert_eq!(saved[0].bumped_at, "2024-02-01T12:00:00Z");
+        assert_eq!(store.get("3").await.unwrap().bumped_at, "2024-02-01T12:00:00Z");
+
+        // Nothing changed since, so the backup of the empty file stays
+        store.flush().await.unwrap();
+        assert_eq!(fs::read_to_string(format!("{}.bak", path.display())).unwrap(), "[]");
+    }
+
+    #[tokio::test]
+    async fn truncated_file_is_recovered_from_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("announced.json");
+        let store = JsonStore::open(path.to_str().unwrap()).unwrap();
+        store.mark_all(vec![item("1"), item("2")]).await.unwrap();
+        store.flush().await.unwrap();
+        store.mark(item("3")).await.unwrap();
+        store.flush().await.unwrap();
+        drop(store);
+
+        // Cut off mid-write
+        let content = fs::read_to_string(&path).unwrap();
+        fs::write(&path, &content[..content.len() / 2]).unwrap();
+
+        let store = JsonStore::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(ids(store.load_all().await.unwrap()), ["1", "2"]);
+        assert_eq!(corrupt_files(dir.path()).len(), 1);
+        // The recovered list is written back in place of the bad file
+        assert_eq!(ids(JsonStore::read_seen_file(path.to_str().unwrap()).unwrap()), ["1", "2"]);
+    }
+
+    #[tokio::test]
+    async fn corrupt_file_is_moved_aside_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("announced.json");
+        fs::write(&path, "not json at all").unwrap();
+
+        let store = JsonStore::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(store.len().await, 0);
+
+        let corrupt = corrupt_files(dir.path());
+        assert_eq!(corrupt.len(), 1);
+        assert_eq!(fs::read_to_string(dir.path().join(&corrupt[0])).unwrap(), "not json at all");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[]");
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_store_imports_a_json_announced_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("announced.json");
+        let json = JsonStore::open(path.to_str().unwrap()).unwrap();
+        json.mark_all(vec![item("1"), item("2")]).await.unwrap();
+        json.flush().await.unwrap();
+        drop(json);
+
+        let store = SqliteStore::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(ids(store.load_all().await.unwrap()), ["1", "2"]);
+        store.mark(item("3")).await.unwrap();
+        drop(store);
+        assert_eq!(ids(JsonStore::read_seen_file(&format!("{}.json", path.display())).unwrap()), ["1", "2"]);
+        assert!(!dir.path().join("announced.json.import").exists());
+
+        // Opened as a database from then on
+        let store = SqliteStore::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(ids(store.load_all().await.unwrap()), ["1", "2", "3"]);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_store_rejects_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("announced.json");
+        fs::write(&path, "not json at all").unwrap();
+
+        let e = SqliteStore::open(path.to_str().unwrap()).err().unwrap();
+        assert!(matches!(e, StoreError::NotADatabase(_)), "{}", e);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "not json at all");
+    }
+
+    #[tokio::test]
+    async fn unreadable_backup_starts_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("announced.json");
+        fs::write(&path, "[{\"id\": \"1\"").unwrap();
+        fs::write(format!("{}.bak", path.display()), "{}").unwrap();
+
+        let store = JsonStore::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(store.len().await, 0);
+        assert_eq!(corrupt_files(dir.path()).len(), 1);
+    }
+}
@@ -0,0 +1,76 @@
+use reqwest::Client;
+use serde_json::json;
+use tokio::time::Duration;
+use tracing::{debug, info};
+
+use crate::config::{AnnounceConfig, TelegramConfig};
+use crate::formatting::{format_item, strip_control_codes};
+use crate::notifier::{Notifier, NotifyError};
+use crate::source::Release;
+
+// Telegram rejects messages longer than this
+const MAX_TEXT_LEN: usize = 4096;
+
+// Sends announcements to a chat or channel through the Telegram Bot API
+pub struct TelegramNotifier {
+    client: Client,
+    config: TelegramConfig,
+    announce: AnnounceConfig,
+    dry_run: bool,
+}
+
+impl TelegramNotifier {
+    pub fn new(config: TelegramConfig, announce: AnnounceConfig, dry_run: bool) -> reqwest::Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()?;
+
+        Ok(Self {
+            client,
+            config,
+            announce,
+            dry_run,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for TelegramNotifier {
+    fn name(&self) -> &str {
+        "Telegram"
+    }
+
+    async fn notify(&mut self, item: &Release) -> Result<(), NotifyError> {
+        let mut text = strip_control_codes(&format_item(item, &self.announce, false));
+        if text.len() > MAX_TEXT_LEN {
+            let mut cut = MAX_TEXT_LEN;
+            while !text.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            text.truncate(cut);
+        }
+
+        if self.dry_run {
+            info!("📝 [dry run] Would send to Telegram: {}", text);
+            return Ok(());
+        }
+
+        // Plain text, no parse_mode, so release names can't break Markdown/HTML parsing
+        let url = format!("https://api.telegram.org/bot{}/sendMessage", self.config.bot_token.expose());
+        let body = json!({
+            "chat_id": self.config.chat_id,
+            "text": text,
+            "disable_web_page_preview": true,
+        });
+
+        // The request URL holds the bot token, keep it out of error messages
+        let response = self.client.post(url).json(&body).send().await
+            .map_err(|e| NotifyError::Http(e.without_url()))?;
+        if !response.status().is_success() {
+            return Err(NotifyError::Rejected(response.status()));
+        }
+
+        debug!("✅ Sent ID {} to Telegram", item.id);
+        Ok(())
+    }
+}
@@ -1,79 +1,660 @@
-use reqwest::Client;
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize};
-use tracing::{debug, info, error};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tokio::time::{sleep, Duration};
+use tracing::{debug, info, warn};
+
+use crate::config::{ApiConfig, AuthStyle, ProxyConfig};
+use crate::metrics::{Metrics, METRICS};
+use crate::source::{network_error, Release, Source, SourceError};
+
+// Longest Retry-After honored, anything above is most likely a server bug
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(6 * 3600);
+
+// Retry-After is either a number of seconds or an HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs).min(MAX_RETRY_AFTER));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    // A date in the past means "now"
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default().min(MAX_RETRY_AFTER))
+}
+
+// Query parameters that carry credentials, e.g. UNIT3D's api_token, or a feed passkey
+const SECRET_PARAMS: &[&str] = &["token", "api_token", "apikey", "api_key", "key", "passkey", "rsskey"];
+
+// Hide credentials in a URL before logging it, both known secret query parameters and
+// any other place the configured token shows up (e.g. in links.next of paginated responses)
+fn redact_url(url: &str, token: &str) -> String {
+    let mut redacted = match reqwest::Url::parse(url) {
+        Ok(mut parsed) if parsed.query().is_some() => {
+            let pairs: Vec<(String, String)> = parsed.query_pairs()
+                .map(|(name, value)| {
+                    let value = if SECRET_PARAMS.iter().any(|param| name.eq_ignore_ascii_case(param)) {
+                        "***".to_string()
+                    } else {
+                        value.into_owned()
+                    };
+                    (name.into_owned(), value)
+                })
+                .collect();
+            parsed.query_pairs_mut().clear().extend_pairs(pairs);
+            parsed.to_string()
+        }
+        _ => url.to_string(),
+    };
+
+    if !token.is_empty() {
+        redacted = redacted.replace(token, "***");
+    }
+    redacted
+}
 
 #[derive(Debug, Deserialize)]
 struct ApiResponse {
-    data: Vec<ApiItem>
+    // Parsed item by item, see parse_items
+    data: Vec<serde_json::Value>,
+    links: Option<Links>,
+    meta: Option<Meta>,
 }
 
 #[derive(Debug, Deserialize)]
-pub struct ApiItem {
-    pub id: String,
-    pub attributes: Attributes,
+struct Links {
+    next: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
-pub struct Attributes {
-    pub category: String,
-    pub r#type: String,
-    pub name: String,
-    pub resolution: Option<String>,
-    pub freeleech: String,
-    pub internal: u8,
-    pub double_upload: bool,
-    pub size: u64,
-    pub uploader: String,
-    pub download_link: String,
-    pub bumped_at: String,
+struct Meta {
+    current_page: Option<u64>,
 }
 
+// Convert each item on its own, so one malformed release doesn't drop the whole page
+fn parse_items(data: Vec<serde_json::Value>) -> Vec<Release> {
+    data.into_iter()
+        .filter_map(|value| {
+            let id = value.get("id").map(|id| id.to_string()).unwrap_or_else(|| "?".to_string());
+            match serde_path_to_error::deserialize::<_, Release>(value) {
+                Ok(item) => Some(item),
+                Err(e) => {
+                    warn!("⚠️ Skipping API item {}: {}", id, e);
+                    Metrics::inc(&METRICS.api_items_invalid);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+// Parse the ISO-8601 timestamps emitted by UNIT3D/*arr APIs, with a trailing Z, a
+// timezone offset or no zone at all (assumed UTC)
+pub fn parse_bumped_at(value: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    let value = value.trim();
+
+    match DateTime::parse_from_rfc3339(value) {
+        Ok(timestamp) => Ok(timestamp.with_timezone(&Utc)),
+        Err(e) => {
+            for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"] {
+                if let Ok(timestamp) = NaiveDateTime::parse_from_str(value, format) {
+                    return Ok(timestamp.and_utc());
+                }
+            }
+            Err(e)
+        }
+    }
+}
+
+// Sort items by bumped_at ascending. Items whose timestamp doesn't parse keep their
+// position from the API response instead of being dropped or moved to the front.
+pub fn sort_oldest_first(items: Vec<Release>) -> Vec<Release> {
+    let mut sorted: Vec<Option<Release>> = Vec::with_capacity(items.len());
+    let mut slots = Vec::new();
+    let mut dated = Vec::new();
+
+    for item in items {
+        match parse_bumped_at(&item.attributes.bumped_at) {
+            Ok(timestamp) => {
+                slots.push(sorted.len());
+                dated.push((timestamp, item));
+                sorted.push(None);
+            }
+            Err(e) => {
+                warn!("Failed to parse bumped_at '{}' of ID {}: {}", item.attributes.bumped_at, item.id, e);
+                sorted.push(Some(item));
+            }
+        }
+    }
+
+    dated.sort_by_key(|(timestamp, _)| *timestamp);
+    for (slot, (_, item)) in slots.into_iter().zip(dated) {
+        sorted[slot] = Some(item);
+    }
+
+    sorted.into_iter().flatten().collect()
+}
+
+// The UNIT3D JSON:API source
 pub struct ApiHandler {
     client: Client,
-    url: String,
-    token: String,
+    config: ApiConfig,
+    // Cache validators and body of the last response for the configured URL
+    validators: Mutex<Validators>,
+}
+
+#[derive(Default)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    // Replayed on a 304, so items held back last time are looked at again
+    body: String,
 }
 
 impl ApiHandler {
-    pub fn new(url: String, token: String) -> Self {
-        Self {
-            client: Client::new(),
-            url,
-            token,
-        }
-    }
-
-    pub async fn fetch_messages(&self) -> Vec<ApiItem> {
-        info!("⬇️ Fetching API {} ...", &self.url);
-
-        match self.client.get(&self.url)
-            .header("Authorization", format!("Bearer {}", self.token))
-            .send()
-            .await
-        {
-            Ok(response) => {
-                let body = match response.text().await {
-                    Ok(text) => text,
-                    Err(e) => {
-                        error!("Failed to read response body: {}", e);
-                        return vec![];
-                    }
-                };
+    pub fn new(config: ApiConfig, proxy: Option<&ProxyConfig>) -> Result<Self, SourceError> {
+        // Without a timeout a hung tracker would stall fetches forever
+        let timeout = Duration::from_secs(config.timeout_secs);
+        // reqwest sends no User-Agent by default, which some trackers block
+        let mut builder = Client::builder()
+            .connect_timeout(timeout)
+            .timeout(timeout)
+            .user_agent(concat!("announcarr/", env!("CARGO_PKG_VERSION")))
+            .default_headers(api_headers(&config));
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy.url.expose()).map_err(SourceError::Client)?);
+        }
+        if let Some(path) = &config.ca_cert {
+            let pem = std::fs::read(path)
+                .map_err(|e| SourceError::CaCert(path.clone(), e))?;
+            builder = builder.add_root_certificate(reqwest::Certificate::from_pem(&pem).map_err(SourceError::Client)?);
+        }
+        if config.insecure_tls {
+            warn!("⚠️ TLS certificate verification is DISABLED for the API, never use insecure_tls in production");
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        let client = builder.build().map_err(SourceError::Client)?;
+
+        Ok(Self {
+            client,
+            config,
+            validators: Mutex::new(Validators::default()),
+        })
+    }
 
-                debug!("Full API response body: {}", body);
+    // Fetch with retries, backing off exponentially between attempts
+    pub async fn fetch_messages(&self) -> Result<Vec<Release>, SourceError> {
+        let result = self.fetch_all_pages().await;
+        record_fetch(&result);
+        result
+    }
+
+    // Fetch all pages, following links.next up to the configured page cap
+    async fn fetch_all_pages(&self) -> Result<Vec<Release>, SourceError> {
+        let mut items = Vec::new();
+        let mut url = self.config.url.to_string();
+
+        for page in 1..=self.config.max_pages.max(1) {
+            let body = self.fetch_body(&url).await?;
+            let response = serde_json::from_str::<ApiResponse>(&body).map_err(SourceError::Parse)?;
+            let current_page = response.meta.and_then(|meta| meta.current_page).unwrap_or(page as u64);
+            debug!("Fetched page {} with {} items", current_page, response.data.len());
+            items.extend(parse_items(response.data));
+
+            match response.links.and_then(|links| links.next) {
+                Some(next) => url = next,
+                None => break,
+            }
+        }
+
+        Ok(items)
+    }
+
+    // GET a URL with the configured retries and credentials, returning the body. When the
+    // configured URL is unchanged since the last fetch (304 Not Modified) that body is reused.
+    pub async fn fetch_body(&self, url: &str) -> Result<String, SourceError> {
+        let mut attempt = 0;
+
+        loop {
+            match self.fetch_once(url).await {
+                Ok(response) => return Ok(response),
+                Err(e) if e.is_retryable() && attempt < self.config.max_retries => {
+                    let backoff = match e {
+                        SourceError::RetryLater(_, Some(retry_after)) => retry_after,
+                        _ => Duration::from_millis(self.config.base_backoff_ms.saturating_mul(2u64.saturating_pow(attempt))),
+                    };
+                    attempt += 1;
+                    warn!("API fetch failed: {}, retry {}/{} in {}ms", e, attempt, self.config.max_retries, backoff.as_millis());
+                    sleep(backoff).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
 
-                match serde_json::from_str::<ApiResponse>(&body) {
-                    Ok(api_response) => api_response.data,
-                    Err(e) => {
-                        error!("Failed to parse API response: {}", e);
-                        vec![]
+    async fn fetch_once(&self, url: &str) -> Result<String, SourceError> {
+        info!("⬇️ Fetching API {} ...", redact_url(url, self.config.token.expose()));
+
+        let mut request = self.client.get(url);
+        // Feeds usually carry the passkey in the URL and have no token
+        let token = self.config.token.expose();
+        if !token.is_empty() {
+            match &self.config.auth_style {
+                AuthStyle::Bearer if !self.config.has_authorization_header() => {
+                    request = request.bearer_auth(token);
+                }
+                AuthStyle::Basic if !self.config.has_authorization_header() => {
+                    request = match token.split_once(':') {
+                        Some((user, password)) => request.basic_auth(user, Some(password)),
+                        None => request.basic_auth(token, None::<&str>),
+                    };
+                }
+                AuthStyle::Bearer | AuthStyle::Basic => {}
+                AuthStyle::Header(name) => request = request.header(name.as_str(), token),
+                // links.next usually carries the parameter already
+                AuthStyle::Query(name) if !has_query_param(url, name) => {
+                    request = request.query(&[(name.as_str(), token)]);
+                }
+                AuthStyle::Query(_) => {}
+            }
+        }
+        // Later pages move as new items arrive, only the configured URL is worth revalidating
+        let conditional = url == self.config.url;
+        if conditional {
+            // The validators are only ever stored together with the body they belong to
+            let validators = self.validators();
+            if let Some(etag) = &validators.etag {
+                request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &validators.last_modified {
+                request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let response = request.send().await.map_err(network_error)?;
+
+        let status = response.status();
+        if status == StatusCode::NOT_MODIFIED && conditional {
+            debug!("API unchanged since the last fetch (HTTP 304), reusing the previous response");
+            return Ok(self.validators().body.clone());
+        }
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            return Err(SourceError::Auth(status));
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+            let retry_after = response.headers().get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after);
+            return Err(SourceError::RetryLater(status, retry_after));
+        }
+        if !status.is_success() {
+            return Err(SourceError::Status(status));
+        }
+        debug!("API responded with HTTP {}", status);
+
+        let header = |name| response.headers().get(name)
+            .and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok())
+            .map(str::to_string);
+        let etag = header(reqwest::header::ETAG);
+        let last_modified = header(reqwest::header::LAST_MODIFIED);
+
+        let body = response.text().await.map_err(SourceError::Network)?;
+        debug!("Full API response body: {}", body);
+
+        // Only remember the validators once the body actually arrived
+        if conditional {
+            *self.validators() = Validators { etag, last_modified, body: body.clone() };
+        }
+
+        Ok(body)
+    }
+
+    fn validators(&self) -> std::sync::MutexGuard<'_, Validators> {
+        // The validators are only ever replaced whole, a poisoned lock still holds a valid pair
+        self.validators.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn has_query_param(url: &str, name: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|url| url.query_pairs().any(|(param, _)| param == name))
+}
+
+// The configured api_headers, which replace the default User-Agent where they overlap.
+// Names and values are checked when the config is validated.
+fn api_headers(config: &ApiConfig) -> HeaderMap {
+    config.api_headers.iter()
+        .filter_map(|(name, value)| Some((
+            HeaderName::from_bytes(name.as_bytes()).ok()?,
+            HeaderValue::from_str(value.expose()).ok()?,
+        )))
+        .collect()
+}
+
+// Count a finished fetch, after retries, in the metrics
+pub fn record_fetch<T>(result: &Result<T, SourceError>) {
+    match result {
+        Ok(_) => {
+            Metrics::inc(&METRICS.api_fetch_success);
+            let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+            Metrics::set(&METRICS.last_fetch_success, now.as_secs());
+        }
+        Err(_) => Metrics::inc(&METRICS.api_fetch_failure),
+    }
+}
+
+#[async_trait]
+impl Source for ApiHandler {
+    fn name(&self) -> &str {
+        "UNIT3D"
+    }
+
+    async fn fetch(&self) -> Result<Vec<Release>, SourceError> {
+        self.fetch_messages().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::Freeleech;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // One page of UNIT3D's /api/torrents/filter as trackers return it
+    const FIXTURE: &str = r#"{
+        "data": [
+            {
+                "type": "torrent",
+                "id": "184302",
+                "attributes": {
+                    "meta": { "poster": "https://image.tmdb.org/t/p/w92/poster.jpg", "genres": "Drama" },
+                    "name": "Some.Movie.2023.1080p.BluRay.DTS-HD.MA.5.1.x264-GROUP",
+                    "release_year": 2023,
+                    "category": "Movie",
+                    "type": "Encode",
+                    "resolution": "1080p",
+                    "media_info": null,
+                    "bd_info": null,
+                    "description": "Encoded from the retail disc.",
+                    "info_hash": "a1b2c3d4e5f60718293a4b5c6d7e8f9012345678",
+                    "size": 15032385536,
+                    "num_file": 1,
+                    "freeleech": "25%",
+                    "double_upload": false,
+                    "internal": 1,
+                    "uploader": "uploader42",
+                    "seeders": 12,
+                    "leechers": 3,
+                    "times_completed": 40,
+                    "tmdb_id": 550,
+                    "imdb_id": 137523,
+                    "tvdb_id": 0,
+                    "mal_id": 0,
+                    "igdb_id": 0,
+                    "category_id": 1,
+                    "type_id": 3,
+                    "resolution_id": 3,
+                    "created_at": "2024-01-01T11:58:02.000000Z",
+                    "bumped_at": "2024-01-01T12:00:00.000000Z",
+                    "download_link": "https://tracker.example/torrent/download/184302.0123456789abcdef",
+                    "magnet_link": null,
+                    "details_link": "https://tracker.example/torrents/184302"
+                }
+            },
+            {
+                "type": "torrent",
+                "id": "184301",
+                "attributes": {
+                    "name": "Some.Show.S01E01.720p.WEB-DL.DDP5.1.H.264-GROUP",
+                    "category": "TV",
+                    "type": "WEB-DL",
+                    "resolution": "720p",
+                    "size": "1288490189",
+                    "freeleech": false,
+                    "double_upload": "1",
+                    "internal": 0,
+                    "uploader": "Anonymous",
+                    "tmdb_id": "",
+                    "bumped_at": "2024-01-01T11:00:00.000000Z",
+                    "download_link": "https://tracker.example/torrent/download/184301.0123456789abcdef"
+                }
+            }
+        ],
+        "links": {
+            "first": "https://tracker.example/api/torrents/filter?page=1",
+            "last": null,
+            "prev": null,
+            "next": null
+        },
+        "meta": { "current_page": 1, "from": 1, "path": "https://tracker.example/api/torrents/filter", "per_page": 25, "to": 2 }
+    }"#;
+
+    fn config(url: &str, extra: serde_json::Value) -> ApiConfig {
+        let mut config = serde_json::json!({ "url": url, "max_retries": 0, "base_backoff_ms": 1 });
+        config.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(config).unwrap()
+    }
+
+    // An HTTP server answering one request per connection with the given responses in
+    // turn, recording each request it received
+    async fn serve(responses: Vec<Vec<u8>>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/torrents", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let received = Arc::clone(&requests);
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    if n == 0 {
+                        break;
                     }
+                    request.extend_from_slice(&buf[..n]);
                 }
+                received.lock().unwrap().push(String::from_utf8_lossy(&request).into_owned());
+                socket.write_all(&response).await.unwrap();
+                socket.shutdown().await.unwrap();
             }
-            Err(e) => {
-                error!("Failed to fetch messages from API: {}", e);
-                vec![]
+        });
+        (url, requests)
+    }
+
+    // An HTTP server that accepts connections without ever answering
+    async fn silent_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/torrents", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                connections.push(socket);
             }
+        });
+        url
+    }
+
+    fn response(status: &str, headers: &[&str], body: impl AsRef<[u8]>) -> Vec<u8> {
+        let body = body.as_ref();
+        let mut response = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+        for header in headers {
+            response.push_str(header);
+            response.push_str("\r\n");
         }
+        response.push_str("\r\n");
+
+        let mut response = response.into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    fn listing(ids: &[&str]) -> String {
+        let data: Vec<serde_json::Value> = ids.iter()
+            .map(|id| serde_json::json!({
+                "id": id,
+                "attributes": {
+                    "name": format!("Release.{}", id),
+                    "download_link": format!("https://tracker.example/torrent/download/{}.passkey", id),
+                    "bumped_at": "2024-01-01T12:00:00Z",
+                },
+            }))
+            .collect();
+        serde_json::json!({ "data": data }).to_string()
+    }
+
+    fn ids(releases: &[Release]) -> Vec<&str> {
+        releases.iter().map(|release| release.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn realistic_response_is_parsed() {
+        let (url, requests) = serve(vec![response("200 OK", &["Content-Type: application/json"], FIXTURE)]).await;
+        let handler = ApiHandler::new(config(&url, serde_json::json!({ "token": "secret" })), None).unwrap();
+
+        let releases = handler.fetch_messages().await.unwrap();
+        assert_eq!(ids(&releases), ["184302", "184301"]);
+
+        let movie = &releases[0].attributes;
+        assert_eq!(movie.name, "Some.Movie.2023.1080p.BluRay.DTS-HD.MA.5.1.x264-GROUP");
+        assert_eq!(movie.category.as_deref(), Some("Movie"));
+        assert_eq!(movie.r#type.as_deref(), Some("Encode"));
+        assert_eq!(movie.resolution.as_deref(), Some("1080p"));
+        assert_eq!(movie.size, 15_032_385_536);
+        assert_eq!(movie.freeleech, Some(Freeleech::Partial(25)));
+        assert_eq!(movie.internal, Some(true));
+        assert_eq!(movie.double_upload, Some(false));
+        assert_eq!(movie.uploader.as_deref(), Some("uploader42"));
+        assert_eq!(movie.imdb_id.as_deref(), Some("137523"));
+        assert_eq!(movie.tmdb_id.as_deref(), Some("550"));
+        assert_eq!(movie.download_link, "https://tracker.example/torrent/download/184302.0123456789abcdef");
+        assert_eq!(movie.bumped_at, "2024-01-01T12:00:00.000000Z");
+
+        // Stringly typed fields from other UNIT3D versions
+        let show = &releases[1].attributes;
+        assert_eq!(show.size, 1_288_490_189);
+        assert_eq!(show.freeleech, Some(Freeleech::No));
+        assert_eq!(show.double_upload, Some(true));
+        assert_eq!(show.internal, Some(false));
+        assert_eq!(show.tmdb_id, None);
+
+        let request = requests.lock().unwrap()[0].to_ascii_lowercase();
+        assert!(request.starts_with("get /api/torrents "), "{}", request);
+        assert!(request.contains("authorization: bearer secret\r\n"), "{}", request);
+    }
+
+    #[tokio::test]
+    async fn malformed_json_fails_without_retrying() {
+        let (url, requests) = serve(vec![response("200 OK", &[], "{\"data\": [")]).await;
+        let handler = ApiHandler::new(config(&url, serde_json::json!({ "max_retries": 2 })), None).unwrap();
+
+        let result = handler.fetch_messages().await;
+        assert!(matches!(result, Err(SourceError::Parse(_))), "{:?}", result);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn empty_body_fails_to_parse() {
+        let (url, _) = serve(vec![response("200 OK", &[], "")]).await;
+        let handler = ApiHandler::new(config(&url, serde_json::json!({})), None).unwrap();
+
+        let result = handler.fetch_messages().await;
+        assert!(matches!(result, Err(SourceError::Parse(_))), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn rejected_credentials_fail_without_retrying() {
+        for status in ["401 Unauthorized", "403 Forbidden"] {
+            let (url, requests) = serve(vec![response(status, &[], "")]).await;
+            let handler = ApiHandler::new(config(&url, serde_json::json!({ "max_retries": 2 })), None).unwrap();
+
+            let result = handler.fetch_messages().await;
+            assert!(matches!(result, Err(SourceError::Auth(_))), "{:?}", result);
+            assert_eq!(requests.lock().unwrap().len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried() {
+        let (url, requests) = serve(vec![
+            response("500 Internal Server Error", &[], ""),
+            response("500 Internal Server Error", &[], ""),
+            response("200 OK", &[], listing(&["1"])),
+        ]).await;
+        let handler = ApiHandler::new(config(&url, serde_json::json!({ "max_retries": 2 })), None).unwrap();
+
+        let releases = handler.fetch_messages().await.unwrap();
+        assert_eq!(ids(&releases), ["1"]);
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn server_errors_fail_once_retries_run_out() {
+        let (url, requests) = serve(vec![response("500 Internal Server Error", &[], ""); 3]).await;
+        let handler = ApiHandler::new(config(&url, serde_json::json!({ "max_retries": 2 })), None).unwrap();
+
+        let result = handler.fetch_messages().await;
+        assert!(matches!(result, Err(SourceError::Status(StatusCode::INTERNAL_SERVER_ERROR))), "{:?}", result);
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn not_modified_reuses_the_previous_listing() {
+        let (url, requests) = serve(vec![
+            response("200 OK", &["ETag: \"v1\"", "Last-Modified: Mon, 01 Jan 2024 12:00:00 GMT"], listing(&["1", "2"])),
+            response("304 Not Modified", &["ETag: \"v1\""], ""),
+        ]).await;
+        let handler = ApiHandler::new(config(&url, serde_json::json!({})), None).unwrap();
+
+        assert_eq!(ids(&handler.fetch_messages().await.unwrap()), ["1", "2"]);
+        assert_eq!(ids(&handler.fetch_messages().await.unwrap()), ["1", "2"]);
+
+        let requests = requests.lock().unwrap();
+        assert!(!requests[0].to_ascii_lowercase().contains("if-none-match"), "{}", requests[0]);
+        let revalidation = requests[1].to_ascii_lowercase();
+        assert!(revalidation.contains("if-none-match: \"v1\"\r\n"), "{}", revalidation);
+        assert!(revalidation.contains("if-modified-since: mon, 01 jan 2024 12:00:00 gmt\r\n"), "{}", revalidation);
+    }
+
+    #[tokio::test]
+    async fn unreachable_server_is_a_network_error() {
+        // Nothing listens on the port once the listener is gone
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/torrents", listener.local_addr().unwrap());
+        drop(listener);
+        let handler = ApiHandler::new(config(&url, serde_json::json!({})), None).unwrap();
+
+        let result = handler.fetch_messages().await;
+        assert!(matches!(&result, Err(SourceError::Network(e)) if e.is_connect()), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn gzip_response_is_decoded() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, listing(&["1", "2"]).as_bytes()).unwrap();
+        let body = encoder.finish().unwrap();
+
+        let (url, requests) = serve(vec![response("200 OK", &["Content-Encoding: gzip"], body)]).await;
+        let handler = ApiHandler::new(config(&url, serde_json::json!({})), None).unwrap();
+
+        assert_eq!(ids(&handler.fetch_messages().await.unwrap()), ["1", "2"]);
+        let request = requests.lock().unwrap()[0].to_ascii_lowercase();
+        assert!(request.contains("accept-encoding: gzip"), "{}", request);
+    }
+
+    #[tokio::test]
+    async fn slow_server_times_out() {
+        let url = silent_server().await;
+        let handler = ApiHandler::new(config(&url, serde_json::json!({ "timeout_secs": 1 })), None).unwrap();
+
+        let started = std::time::Instant::now();
+        let result = handler.fetch_messages().await;
+        assert!(matches!(&result, Err(SourceError::Network(e)) if e.is_timeout()), "{:?}", result);
+        assert!(started.elapsed() < Duration::from_secs(5));
     }
 }